@@ -2,24 +2,584 @@ import type { Principal } from '@dfinity/principal';
 import type { ActorMethod } from '@dfinity/agent';
 import type { IDL } from '@dfinity/candid';
 
+export interface Account {
+  'owner' : Principal,
+  'subaccount' : [] | [Uint8Array | number[]],
+}
+export interface AccountConflict {
+  'account_id' : string,
+  'principals' : Array<Principal>,
+}
+export interface AccountIndexStats {
+  'last_unresolved' : bigint,
+  'last_rebuilt' : bigint,
+  'rebuild_processed' : [] | [bigint],
+  'subaccount_range' : number,
+  'indexed_accounts' : bigint,
+}
+export interface Collection {
+  'name' : string,
+  'canister_id' : Principal,
+  'enabled' : boolean,
+  'reward_weight_bps' : number,
+  'standard' : CollectionStandard,
+}
+export interface CollectionHolding {
+  'collection' : Principal,
+  'count' : bigint,
+  'stale' : [] | [StaleHolding],
+}
+export interface CollectionImportReport {
+  'sha256' : string,
+  'collection' : Principal,
+  'diff' : ImportDiff,
+  'file' : CsvFileReport,
+  'quality' : CollectionQuality,
+  'mode' : ImportMode,
+  'holders' : bigint,
+}
+export interface CollectionQuality {
+  'collection' : Principal,
+  'source' : QualitySource,
+  'token_conflicts' : Array<TokenConflict>,
+  'supply_excess_count' : bigint,
+  'supply' : [] | [bigint],
+  'supply_excesses' : Array<SupplyExcess>,
+  'token_conflict_count' : bigint,
+  'checked_at' : bigint,
+}
+export interface CollectionRefreshReport {
+  'owners_unresolved' : bigint,
+  'collection' : Principal,
+  'error' : [] | [string],
+  'unresolved_owners' : Array<string>,
+  'tokens_unresolved' : bigint,
+  'tokens_attributed' : bigint,
+}
+export type CollectionStandard = { 'Ext' : null } |
+  { 'AlbumTokens' : null } |
+  { 'DakuTokens' : null };
+export interface CountMismatch {
+  'principal' : Principal,
+  'number_of_tokens' : bigint,
+  'line' : bigint,
+  'token_ids' : bigint,
+}
+export interface CsvFileReport {
+  'rejected_rows' : bigint,
+  'accepted_rows' : bigint,
+  'mismatches' : Array<CountMismatch>,
+  'rejected' : Array<RejectedRow>,
+  'mismatched_rows' : bigint,
+  'rows_without_principal' : bigint,
+}
+export interface CsvImportReport {
+  'gg' : CsvFileReport,
+  'daku' : CsvFileReport,
+  'holders' : bigint,
+}
+export interface DataQualityReport {
+  'account_conflicts' : Array<AccountConflict>,
+  'account_conflict_count' : bigint,
+  'collections' : Array<CollectionQuality>,
+}
+export interface Exclusion {
+  'note' : string,
+  'added_at' : bigint,
+  'added_by' : Principal,
+}
+export interface ExclusionConfig { 'auto_exclude_canisters' : boolean }
+export type ExclusionReason = { 'Listed' : string } |
+  { 'CanisterPrincipal' : null };
+export interface GetAllTokensResponse {
+  'daku_count' : bigint,
+  'errors' : Array<string>,
+  'collections' : Array<CollectionHolding>,
+  'gg_album_count' : bigint,
+  'total_count' : bigint,
+}
+export interface HolderAt {
+  'holdings' : Array<CollectionHolding>,
+  'total_count' : bigint,
+  'taken_at' : bigint,
+  'snapshot_id' : bigint,
+}
+export interface HolderChange {
+  'principal' : Principal,
+  'after' : bigint,
+  'before' : bigint,
+}
 export interface HolderInfo {
   'gg_count' : bigint,
+  'excluded' : [] | [ExclusionReason],
   'daku_count' : bigint,
   'last_updated' : bigint,
+  'collections' : [] | [Array<CollectionHolding>],
+  'reward_recipient' : [] | [Account],
   'total_count' : bigint,
+  'set_bonus' : [] | [SetBonus],
+}
+export interface HolderSnapshot {
+  'holders' : Array<[Principal, HolderInfo]>,
+  'taken_at' : bigint,
+  'snapshot_id' : bigint,
+}
+export interface HoldersPage {
+  'total' : bigint,
+  'next_cursor' : [] | [Principal],
+  'holders' : Array<[Principal, HolderInfo]>,
+  'taken_at' : bigint,
+  'snapshot_id' : bigint,
+}
+export interface ImportDiff {
+  'changed_holders' : bigint,
+  'added_holders' : bigint,
+  'changes' : Array<HolderChange>,
+  'unchanged_holders' : bigint,
+  'removed_holders' : bigint,
+}
+export type ImportMode = { 'DryRun' : null } |
+  { 'Replace' : null } |
+  { 'Merge' : null };
+export interface ImportProgress {
+  'rows_read' : bigint,
+  'session_id' : bigint,
+  'collection' : Principal,
+  'bytes_received' : bigint,
+  'chunks' : bigint,
+}
+export interface InitArgs { 'admins' : Array<Principal> }
+export interface LinkProposal {
+  'target' : Principal,
+  'proposer' : Principal,
+  'expires_at' : bigint,
+  'proposed_at' : bigint,
+}
+export interface LinkedWallet { 'principal' : Principal, 'linked_at' : bigint }
+export interface LinkedWallets {
+  'pending' : Array<LinkProposal>,
+  'primary' : Principal,
+  'linked' : Array<LinkedWallet>,
 }
 export interface NFTProgress {
   'in_progress' : boolean,
   'count' : bigint,
   'last_updated' : bigint,
 }
+export type PoolSplit = { 'Equal' : null } |
+  { 'Proportional' : null };
+export type QualitySource = { 'CsvImport' : null } |
+  { 'Registry' : null };
+export interface RecipientChange {
+  'changed_at' : bigint,
+  'recipient' : [] | [Account],
+}
+export interface RefreshJob {
+  'status' : RefreshJobStatus,
+  'total' : bigint,
+  'trigger' : RefreshTrigger,
+  'cursor' : [] | [Principal],
+  'mode' : RefreshMode,
+  'job_id' : bigint,
+  'updated' : bigint,
+  'processed' : bigint,
+  'last_progress_at' : bigint,
+  'started_at' : bigint,
+  'finished_at' : [] | [bigint],
+}
+export type RefreshJobStatus = { 'Interrupted' : string } |
+  { 'Running' : null } |
+  { 'Cancelled' : null } |
+  { 'Completed' : null };
+export type RefreshMode = { 'Registry' : null } |
+  { 'PerHolder' : null };
+export type RefreshOutcome = {
+    'Ok' : { 'tokens_unresolved' : [] | [bigint], 'holders' : bigint }
+  } |
+  { 'Err' : string };
+export interface RefreshRun {
+  'trigger' : RefreshTrigger,
+  'mode' : RefreshMode,
+  'duration_ns' : bigint,
+  'outcome' : RefreshOutcome,
+  'started_at' : bigint,
+}
+export interface RefreshSchedule {
+  'armed_at' : [] | [bigint],
+  'mode' : RefreshMode,
+  'interval_secs' : bigint,
+  'recent_runs' : Array<RefreshRun>,
+  'next_run_at' : [] | [bigint],
+  'paused' : boolean,
+}
+export type RefreshTrigger = { 'Timer' : null } |
+  { 'Manual' : null };
+export interface RegistryRefreshReport {
+  'collections' : Array<CollectionRefreshReport>,
+  'tokens_unresolved' : bigint,
+  'tokens_attributed' : bigint,
+  'holders' : bigint,
+}
+export interface RejectedRow { 'line' : bigint, 'reason' : string }
+export type RejectionCode = { 'NoError' : null } |
+  { 'CanisterError' : null } |
+  { 'SysTransient' : null } |
+  { 'DestinationInvalid' : null } |
+  { 'Unknown' : null } |
+  { 'SysFatal' : null } |
+  { 'CanisterReject' : null };
+export type RewardBasis = {
+    'TimeWeighted' : { 'window_start' : bigint, 'window_end' : bigint }
+  } |
+  { 'Snapshot' : { 'snapshot_id' : [] | [bigint] } };
+export interface RewardEntry {
+  'principal' : Principal,
+  'recipient' : Account,
+  'tokens_milli' : bigint,
+  'score_milli' : bigint,
+  'capped' : boolean,
+  'amount' : bigint,
+  'set_bonus' : [] | [SetBonus],
+  'linked_wallets' : Array<Principal>,
+}
+export interface RewardPlan {
+  'distributed' : bigint,
+  'plan_hash' : string,
+  'undistributed' : bigint,
+  'entries' : Array<RewardEntry>,
+  'pool_amount' : bigint,
+  'basis' : RewardBasis,
+  'policy' : RewardPolicy,
+}
+export interface RewardPolicy {
+  'tiers' : Array<RewardTier>,
+  'set_rules' : [] | [Array<SetRule>],
+  'split' : PoolSplit,
+  'max_per_wallet' : [] | [bigint],
+  'min_tokens' : bigint,
+  'weighting_window_secs' : [] | [bigint],
+}
+export interface RewardTier { 'multiplier_bps' : number, 'min_tokens' : bigint }
+export interface SelfRefreshConfig {
+  'window_secs' : bigint,
+  'enabled' : boolean,
+  'max_per_window' : number,
+  'cooldown_secs' : bigint,
+}
+export interface SetBonus { 'rule' : string, 'multiplier_bps' : number }
+export interface SetRequirement {
+  'collection' : Principal,
+  'min_count' : bigint,
+}
+export interface SetRule {
+  'name' : string,
+  'multiplier_bps' : number,
+  'requirements' : Array<SetRequirement>,
+}
+export interface SnapshotRetention {
+  'max_age_secs' : [] | [bigint],
+  'max_snapshots' : [] | [number],
+}
+export type SnapshotSource = { 'CsvImport' : null } |
+  { 'Registry' : null } |
+  { 'PerHolder' : null };
+export interface SnapshotSummary {
+  'source' : SnapshotSource,
+  'collections' : Array<Principal>,
+  'holders' : bigint,
+  'changes' : [] | [bigint],
+  'total_tokens' : bigint,
+  'taken_at' : bigint,
+  'snapshot_id' : bigint,
+}
+export interface StaleHolding {
+  'failed_at' : bigint,
+  'error' : string,
+  'confirmed_at' : [] | [bigint],
+}
+export interface SupplyExcess {
+  'count' : bigint,
+  'supply' : bigint,
+  'holder' : Principal,
+}
+export interface TokenConflict {
+  'token_index' : number,
+  'owners' : Array<Principal>,
+}
+export type WalletError = {
+    'SnapshotChanged' : { 'requested' : bigint, 'current' : bigint }
+  } |
+  { 'CsvParse' : { 'line' : bigint, 'reason' : string } } |
+  { 'CollectionNotFound' : Principal } |
+  { 'InvalidPrincipal' : string } |
+  { 'CooldownActive' : { 'retry_after_secs' : bigint } } |
+  { 'Unauthorized' : null } |
+  { 'CanisterUnreachable' : { 'msg' : string, 'code' : RejectionCode } } |
+  { 'RefreshBudgetExhausted' : { 'retry_after_secs' : bigint } } |
+  { 'InvalidArgument' : string } |
+  { 'RefreshInProgress' : null } |
+  { 'DecodeFailed' : string };
+export interface WeightedHolder {
+  'principal' : Principal,
+  'holdings' : Array<WeightedHolding>,
+  'total_average_milli' : bigint,
+}
+export interface WeightedHolding {
+  'collection' : Principal,
+  'average_milli' : bigint,
+}
+export interface WeightedHoldings {
+  'window_start' : bigint,
+  'snapshots_used' : number,
+  'holders' : Array<WeightedHolder>,
+  'window_end' : bigint,
+  'covered_from' : [] | [bigint],
+}
 export interface _SERVICE {
+  'add_admin' : ActorMethod<
+    [Principal],
+    { 'Ok' : null } |
+      { 'Err' : WalletError }
+  >,
+  'add_exclusion' : ActorMethod<
+    [Principal, string],
+    { 'Ok' : null } |
+      { 'Err' : WalletError }
+  >,
+  'append_chunk' : ActorMethod<
+    [bigint, Uint8Array | number[]],
+    { 'Ok' : ImportProgress } |
+      { 'Err' : WalletError }
+  >,
+  'begin_import' : ActorMethod<
+    [Principal],
+    { 'Ok' : bigint } |
+      { 'Err' : WalletError }
+  >,
+  'bulk_update_nft_counts' : ActorMethod<
+    [Array<Principal>],
+    { 'Ok' : Array<[Principal, bigint]> } |
+      { 'Err' : WalletError }
+  >,
+  'cancel_refresh_job' : ActorMethod<
+    [],
+    { 'Ok' : [] | [RefreshJob] } |
+      { 'Err' : WalletError }
+  >,
+  'cancel_wallet_link' : ActorMethod<
+    [Principal],
+    { 'Ok' : boolean } |
+      { 'Err' : WalletError }
+  >,
+  'clear_reward_recipient' : ActorMethod<
+    [],
+    { 'Ok' : Account } |
+      { 'Err' : WalletError }
+  >,
+  'commit_import' : ActorMethod<
+    [bigint, string, ImportMode],
+    { 'Ok' : CollectionImportReport } |
+      { 'Err' : WalletError }
+  >,
+  'compute_reward_plan' : ActorMethod<[bigint], RewardPlan>,
+  'confirm_wallet_link' : ActorMethod<
+    [Principal],
+    { 'Ok' : LinkedWallets } |
+      { 'Err' : WalletError }
+  >,
+  'get_account_ids_of' : ActorMethod<[Principal], Array<string>>,
+  'get_account_index_stats' : ActorMethod<[], AccountIndexStats>,
+  'get_admins' : ActorMethod<[], Array<Principal>>,
   'get_all_holders' : ActorMethod<[], Array<[Principal, HolderInfo]>>,
   'get_all_nft_counts' : ActorMethod<[], Array<[Principal, NFTProgress]>>,
+  'get_all_tokens' : ActorMethod<
+    [string],
+    { 'Ok' : GetAllTokensResponse } |
+      { 'Err' : WalletError }
+  >,
   'get_balance' : ActorMethod<[Principal], bigint>,
+  'get_collections' : ActorMethod<[], Array<Collection>>,
+  'get_data_quality_report' : ActorMethod<[], DataQualityReport>,
+  'get_debug_info' : ActorMethod<[], Array<string>>,
+  'get_exclusion_config' : ActorMethod<[], ExclusionConfig>,
+  'get_exclusion_reason' : ActorMethod<[Principal], [] | [ExclusionReason]>,
+  'get_exclusions' : ActorMethod<[], Array<[Principal, Exclusion]>>,
+  'get_holder_at' : ActorMethod<[Principal, bigint], [] | [HolderAt]>,
+  'get_holders_page' : ActorMethod<
+    [[] | [bigint], [] | [Principal], number],
+    { 'Ok' : HoldersPage } |
+      { 'Err' : WalletError }
+  >,
+  'get_holders_snapshot' : ActorMethod<[], HolderSnapshot>,
+  'get_linked_wallets' : ActorMethod<[Principal], LinkedWallets>,
   'get_nft_count' : ActorMethod<[Principal], NFTProgress>,
-  'update_all_holders' : ActorMethod<[], bigint>,
-  'update_balance' : ActorMethod<[Principal, bigint], bigint>,
+  'get_nft_registry' : ActorMethod<
+    [string],
+    { 'Ok' : string } |
+      { 'Err' : WalletError }
+  >,
+  'get_owner_of' : ActorMethod<[Principal, number], [] | [Principal]>,
+  'get_refresh_job' : ActorMethod<[], [] | [RefreshJob]>,
+  'get_refresh_schedule' : ActorMethod<[], RefreshSchedule>,
+  'get_reward_policy' : ActorMethod<[], RewardPolicy>,
+  'get_reward_recipient' : ActorMethod<[Principal], Account>,
+  'get_reward_recipient_history' : ActorMethod<
+    [Principal],
+    Array<RecipientChange>
+  >,
+  'get_self_refresh_config' : ActorMethod<[], SelfRefreshConfig>,
+  'get_snapshot_retention' : ActorMethod<[], SnapshotRetention>,
+  'get_tokens_of' : ActorMethod<[Principal, Principal], Uint32Array | number[]>,
+  'get_total_holders' : ActorMethod<[], bigint>,
+  'get_weighted_holdings' : ActorMethod<
+    [bigint],
+    { 'Ok' : WeightedHoldings } |
+      { 'Err' : WalletError }
+  >,
+  'import_collection_csv' : ActorMethod<
+    [Principal, string, ImportMode],
+    { 'Ok' : CollectionImportReport } |
+      { 'Err' : WalletError }
+  >,
+  'is_admin' : ActorMethod<[Principal], boolean>,
+  'is_using_csv_data' : ActorMethod<[], boolean>,
+  'list_snapshots' : ActorMethod<[], Array<SnapshotSummary>>,
+  'load_csv_data' : ActorMethod<
+    [string, string],
+    { 'Ok' : CsvImportReport } |
+      { 'Err' : WalletError }
+  >,
+  'load_test_csv_data' : ActorMethod<
+    [],
+    { 'Ok' : CsvImportReport } |
+      { 'Err' : WalletError }
+  >,
+  'pause_refresh' : ActorMethod<
+    [],
+    { 'Ok' : RefreshSchedule } |
+      { 'Err' : WalletError }
+  >,
+  'propose_wallet_link' : ActorMethod<
+    [Principal],
+    { 'Ok' : LinkProposal } |
+      { 'Err' : WalletError }
+  >,
+  'rebuild_account_index' : ActorMethod<
+    [],
+    { 'Ok' : AccountIndexStats } |
+      { 'Err' : WalletError }
+  >,
+  'refresh_holders_from_registry' : ActorMethod<
+    [],
+    { 'Ok' : RegistryRefreshReport } |
+      { 'Err' : WalletError }
+  >,
+  'refresh_my_holdings' : ActorMethod<
+    [],
+    { 'Ok' : HolderInfo } |
+      { 'Err' : WalletError }
+  >,
+  'remove_admin' : ActorMethod<
+    [Principal],
+    { 'Ok' : boolean } |
+      { 'Err' : WalletError }
+  >,
+  'remove_collection' : ActorMethod<
+    [Principal],
+    { 'Ok' : boolean } |
+      { 'Err' : WalletError }
+  >,
+  'remove_exclusion' : ActorMethod<
+    [Principal],
+    { 'Ok' : boolean } |
+      { 'Err' : WalletError }
+  >,
+  'resolve_account_id' : ActorMethod<[string], [] | [Principal]>,
+  'resume_refresh_job' : ActorMethod<
+    [],
+    { 'Ok' : RefreshJob } |
+      { 'Err' : WalletError }
+  >,
+  'set_auto_exclude_canisters' : ActorMethod<
+    [boolean],
+    { 'Ok' : ExclusionConfig } |
+      { 'Err' : WalletError }
+  >,
+  'set_collection_enabled' : ActorMethod<
+    [Principal, boolean],
+    { 'Ok' : Collection } |
+      { 'Err' : WalletError }
+  >,
+  'set_refresh_interval' : ActorMethod<
+    [bigint, [] | [RefreshMode]],
+    { 'Ok' : RefreshSchedule } |
+      { 'Err' : WalletError }
+  >,
+  'set_reward_policy' : ActorMethod<
+    [RewardPolicy],
+    { 'Ok' : RewardPolicy } |
+      { 'Err' : WalletError }
+  >,
+  'set_reward_recipient' : ActorMethod<
+    [Account],
+    { 'Ok' : Account } |
+      { 'Err' : WalletError }
+  >,
+  'set_self_refresh_config' : ActorMethod<
+    [SelfRefreshConfig],
+    { 'Ok' : SelfRefreshConfig } |
+      { 'Err' : WalletError }
+  >,
+  'set_snapshot_retention' : ActorMethod<
+    [[] | [number], [] | [bigint]],
+    { 'Ok' : SnapshotRetention } |
+      { 'Err' : WalletError }
+  >,
+  'set_subaccount_range' : ActorMethod<
+    [number],
+    { 'Ok' : AccountIndexStats } |
+      { 'Err' : WalletError }
+  >,
+  'set_verified_nft_counts' : ActorMethod<
+    [Principal, bigint, bigint],
+    { 'Ok' : HolderInfo } |
+      { 'Err' : WalletError }
+  >,
+  'test_direct_canister_calls' : ActorMethod<
+    [],
+    { 'Ok' : Array<string> } |
+      { 'Err' : WalletError }
+  >,
+  'test_ext_query' : ActorMethod<
+    [string, string],
+    { 'Ok' : Array<string> } |
+      { 'Err' : WalletError }
+  >,
+  'unlink_wallet' : ActorMethod<
+    [Principal],
+    { 'Ok' : boolean } |
+      { 'Err' : WalletError }
+  >,
+  'update_all_holders' : ActorMethod<
+    [],
+    { 'Ok' : bigint } |
+      { 'Err' : WalletError }
+  >,
+  'update_balance' : ActorMethod<
+    [Principal, bigint],
+    { 'Ok' : bigint } |
+      { 'Err' : WalletError }
+  >,
+  'update_nft_count' : ActorMethod<
+    [Principal],
+    { 'Ok' : bigint } |
+      { 'Err' : WalletError }
+  >,
+  'upsert_collection' : ActorMethod<
+    [Collection],
+    { 'Ok' : null } |
+      { 'Err' : WalletError }
+  >,
 }
 export declare const idlFactory: IDL.InterfaceFactory;
-export declare const init: (args: { IDL: typeof IDL }) => IDL.Type[];
+export declare const init: (args: { IDL: typeof IDL }) => IDL.Type[];
\ No newline at end of file
@@ -1,16 +1,442 @@
 export const idlFactory = ({ IDL }) => {
+  const InitArgs = IDL.Record({ 'admins' : IDL.Vec(IDL.Principal) });
+  const RejectionCode = IDL.Variant({
+    'NoError' : IDL.Null,
+    'CanisterError' : IDL.Null,
+    'SysTransient' : IDL.Null,
+    'DestinationInvalid' : IDL.Null,
+    'Unknown' : IDL.Null,
+    'SysFatal' : IDL.Null,
+    'CanisterReject' : IDL.Null,
+  });
+  const WalletError = IDL.Variant({
+    'SnapshotChanged' : IDL.Record({
+      'requested' : IDL.Nat64,
+      'current' : IDL.Nat64,
+    }),
+    'CsvParse' : IDL.Record({ 'line' : IDL.Nat64, 'reason' : IDL.Text }),
+    'CollectionNotFound' : IDL.Principal,
+    'InvalidPrincipal' : IDL.Text,
+    'CooldownActive' : IDL.Record({ 'retry_after_secs' : IDL.Nat64 }),
+    'Unauthorized' : IDL.Null,
+    'CanisterUnreachable' : IDL.Record({
+      'msg' : IDL.Text,
+      'code' : RejectionCode,
+    }),
+    'RefreshBudgetExhausted' : IDL.Record({ 'retry_after_secs' : IDL.Nat64 }),
+    'InvalidArgument' : IDL.Text,
+    'RefreshInProgress' : IDL.Null,
+    'DecodeFailed' : IDL.Text,
+  });
+  const ImportProgress = IDL.Record({
+    'rows_read' : IDL.Nat64,
+    'session_id' : IDL.Nat64,
+    'collection' : IDL.Principal,
+    'bytes_received' : IDL.Nat64,
+    'chunks' : IDL.Nat64,
+  });
+  const RefreshJobStatus = IDL.Variant({
+    'Interrupted' : IDL.Text,
+    'Running' : IDL.Null,
+    'Cancelled' : IDL.Null,
+    'Completed' : IDL.Null,
+  });
+  const RefreshTrigger = IDL.Variant({
+    'Timer' : IDL.Null,
+    'Manual' : IDL.Null,
+  });
+  const RefreshMode = IDL.Variant({
+    'Registry' : IDL.Null,
+    'PerHolder' : IDL.Null,
+  });
+  const RefreshJob = IDL.Record({
+    'status' : RefreshJobStatus,
+    'total' : IDL.Nat64,
+    'trigger' : RefreshTrigger,
+    'cursor' : IDL.Opt(IDL.Principal),
+    'mode' : RefreshMode,
+    'job_id' : IDL.Nat64,
+    'updated' : IDL.Nat64,
+    'processed' : IDL.Nat64,
+    'last_progress_at' : IDL.Nat64,
+    'started_at' : IDL.Nat64,
+    'finished_at' : IDL.Opt(IDL.Nat64),
+  });
+  const Account = IDL.Record({
+    'owner' : IDL.Principal,
+    'subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+  });
+  const ImportMode = IDL.Variant({
+    'DryRun' : IDL.Null,
+    'Replace' : IDL.Null,
+    'Merge' : IDL.Null,
+  });
+  const HolderChange = IDL.Record({
+    'principal' : IDL.Principal,
+    'after' : IDL.Nat64,
+    'before' : IDL.Nat64,
+  });
+  const ImportDiff = IDL.Record({
+    'changed_holders' : IDL.Nat64,
+    'added_holders' : IDL.Nat64,
+    'changes' : IDL.Vec(HolderChange),
+    'unchanged_holders' : IDL.Nat64,
+    'removed_holders' : IDL.Nat64,
+  });
+  const CountMismatch = IDL.Record({
+    'principal' : IDL.Principal,
+    'number_of_tokens' : IDL.Nat64,
+    'line' : IDL.Nat64,
+    'token_ids' : IDL.Nat64,
+  });
+  const RejectedRow = IDL.Record({ 'line' : IDL.Nat64, 'reason' : IDL.Text });
+  const CsvFileReport = IDL.Record({
+    'rejected_rows' : IDL.Nat64,
+    'accepted_rows' : IDL.Nat64,
+    'mismatches' : IDL.Vec(CountMismatch),
+    'rejected' : IDL.Vec(RejectedRow),
+    'mismatched_rows' : IDL.Nat64,
+    'rows_without_principal' : IDL.Nat64,
+  });
+  const QualitySource = IDL.Variant({
+    'CsvImport' : IDL.Null,
+    'Registry' : IDL.Null,
+  });
+  const TokenConflict = IDL.Record({
+    'token_index' : IDL.Nat32,
+    'owners' : IDL.Vec(IDL.Principal),
+  });
+  const SupplyExcess = IDL.Record({
+    'count' : IDL.Nat64,
+    'supply' : IDL.Nat64,
+    'holder' : IDL.Principal,
+  });
+  const CollectionQuality = IDL.Record({
+    'collection' : IDL.Principal,
+    'source' : QualitySource,
+    'token_conflicts' : IDL.Vec(TokenConflict),
+    'supply_excess_count' : IDL.Nat64,
+    'supply' : IDL.Opt(IDL.Nat64),
+    'supply_excesses' : IDL.Vec(SupplyExcess),
+    'token_conflict_count' : IDL.Nat64,
+    'checked_at' : IDL.Nat64,
+  });
+  const CollectionImportReport = IDL.Record({
+    'sha256' : IDL.Text,
+    'collection' : IDL.Principal,
+    'diff' : ImportDiff,
+    'file' : CsvFileReport,
+    'quality' : CollectionQuality,
+    'mode' : ImportMode,
+    'holders' : IDL.Nat64,
+  });
+  const SetBonus = IDL.Record({
+    'rule' : IDL.Text,
+    'multiplier_bps' : IDL.Nat32,
+  });
+  const RewardEntry = IDL.Record({
+    'principal' : IDL.Principal,
+    'recipient' : Account,
+    'tokens_milli' : IDL.Nat64,
+    'score_milli' : IDL.Nat64,
+    'capped' : IDL.Bool,
+    'amount' : IDL.Nat64,
+    'set_bonus' : IDL.Opt(SetBonus),
+    'linked_wallets' : IDL.Vec(IDL.Principal),
+  });
+  const RewardBasis = IDL.Variant({
+    'TimeWeighted' : IDL.Record({
+      'window_start' : IDL.Nat64,
+      'window_end' : IDL.Nat64,
+    }),
+    'Snapshot' : IDL.Record({ 'snapshot_id' : IDL.Opt(IDL.Nat64) }),
+  });
+  const RewardTier = IDL.Record({
+    'multiplier_bps' : IDL.Nat32,
+    'min_tokens' : IDL.Nat64,
+  });
+  const SetRequirement = IDL.Record({
+    'collection' : IDL.Principal,
+    'min_count' : IDL.Nat64,
+  });
+  const SetRule = IDL.Record({
+    'name' : IDL.Text,
+    'multiplier_bps' : IDL.Nat32,
+    'requirements' : IDL.Vec(SetRequirement),
+  });
+  const PoolSplit = IDL.Variant({
+    'Equal' : IDL.Null,
+    'Proportional' : IDL.Null,
+  });
+  const RewardPolicy = IDL.Record({
+    'tiers' : IDL.Vec(RewardTier),
+    'set_rules' : IDL.Opt(IDL.Vec(SetRule)),
+    'split' : PoolSplit,
+    'max_per_wallet' : IDL.Opt(IDL.Nat64),
+    'min_tokens' : IDL.Nat64,
+    'weighting_window_secs' : IDL.Opt(IDL.Nat64),
+  });
+  const RewardPlan = IDL.Record({
+    'distributed' : IDL.Nat64,
+    'plan_hash' : IDL.Text,
+    'undistributed' : IDL.Nat64,
+    'entries' : IDL.Vec(RewardEntry),
+    'pool_amount' : IDL.Nat64,
+    'basis' : RewardBasis,
+    'policy' : RewardPolicy,
+  });
+  const LinkProposal = IDL.Record({
+    'target' : IDL.Principal,
+    'proposer' : IDL.Principal,
+    'expires_at' : IDL.Nat64,
+    'proposed_at' : IDL.Nat64,
+  });
+  const LinkedWallet = IDL.Record({
+    'principal' : IDL.Principal,
+    'linked_at' : IDL.Nat64,
+  });
+  const LinkedWallets = IDL.Record({
+    'pending' : IDL.Vec(LinkProposal),
+    'primary' : IDL.Principal,
+    'linked' : IDL.Vec(LinkedWallet),
+  });
+  const AccountIndexStats = IDL.Record({
+    'last_unresolved' : IDL.Nat64,
+    'last_rebuilt' : IDL.Nat64,
+    'rebuild_processed' : IDL.Opt(IDL.Nat64),
+    'subaccount_range' : IDL.Nat32,
+    'indexed_accounts' : IDL.Nat64,
+  });
+  const ExclusionReason = IDL.Variant({
+    'Listed' : IDL.Text,
+    'CanisterPrincipal' : IDL.Null,
+  });
+  const StaleHolding = IDL.Record({
+    'failed_at' : IDL.Nat64,
+    'error' : IDL.Text,
+    'confirmed_at' : IDL.Opt(IDL.Nat64),
+  });
+  const CollectionHolding = IDL.Record({
+    'collection' : IDL.Principal,
+    'count' : IDL.Nat64,
+    'stale' : IDL.Opt(StaleHolding),
+  });
   const HolderInfo = IDL.Record({
     'gg_count' : IDL.Nat64,
+    'excluded' : IDL.Opt(ExclusionReason),
     'daku_count' : IDL.Nat64,
     'last_updated' : IDL.Nat64,
+    'collections' : IDL.Opt(IDL.Vec(CollectionHolding)),
+    'reward_recipient' : IDL.Opt(Account),
     'total_count' : IDL.Nat64,
+    'set_bonus' : IDL.Opt(SetBonus),
   });
   const NFTProgress = IDL.Record({
     'in_progress' : IDL.Bool,
     'count' : IDL.Nat64,
     'last_updated' : IDL.Nat64,
   });
+  const GetAllTokensResponse = IDL.Record({
+    'daku_count' : IDL.Nat64,
+    'errors' : IDL.Vec(IDL.Text),
+    'collections' : IDL.Vec(CollectionHolding),
+    'gg_album_count' : IDL.Nat64,
+    'total_count' : IDL.Nat64,
+  });
+  const CollectionStandard = IDL.Variant({
+    'Ext' : IDL.Null,
+    'AlbumTokens' : IDL.Null,
+    'DakuTokens' : IDL.Null,
+  });
+  const Collection = IDL.Record({
+    'name' : IDL.Text,
+    'canister_id' : IDL.Principal,
+    'enabled' : IDL.Bool,
+    'reward_weight_bps' : IDL.Nat32,
+    'standard' : CollectionStandard,
+  });
+  const AccountConflict = IDL.Record({
+    'account_id' : IDL.Text,
+    'principals' : IDL.Vec(IDL.Principal),
+  });
+  const DataQualityReport = IDL.Record({
+    'account_conflicts' : IDL.Vec(AccountConflict),
+    'account_conflict_count' : IDL.Nat64,
+    'collections' : IDL.Vec(CollectionQuality),
+  });
+  const ExclusionConfig = IDL.Record({ 'auto_exclude_canisters' : IDL.Bool });
+  const Exclusion = IDL.Record({
+    'note' : IDL.Text,
+    'added_at' : IDL.Nat64,
+    'added_by' : IDL.Principal,
+  });
+  const HolderAt = IDL.Record({
+    'holdings' : IDL.Vec(CollectionHolding),
+    'total_count' : IDL.Nat64,
+    'taken_at' : IDL.Nat64,
+    'snapshot_id' : IDL.Nat64,
+  });
+  const HoldersPage = IDL.Record({
+    'total' : IDL.Nat64,
+    'next_cursor' : IDL.Opt(IDL.Principal),
+    'holders' : IDL.Vec(IDL.Tuple(IDL.Principal, HolderInfo)),
+    'taken_at' : IDL.Nat64,
+    'snapshot_id' : IDL.Nat64,
+  });
+  const HolderSnapshot = IDL.Record({
+    'holders' : IDL.Vec(IDL.Tuple(IDL.Principal, HolderInfo)),
+    'taken_at' : IDL.Nat64,
+    'snapshot_id' : IDL.Nat64,
+  });
+  const RefreshOutcome = IDL.Variant({
+    'Ok' : IDL.Record({
+      'tokens_unresolved' : IDL.Opt(IDL.Nat64),
+      'holders' : IDL.Nat64,
+    }),
+    'Err' : IDL.Text,
+  });
+  const RefreshRun = IDL.Record({
+    'trigger' : RefreshTrigger,
+    'mode' : RefreshMode,
+    'duration_ns' : IDL.Nat64,
+    'outcome' : RefreshOutcome,
+    'started_at' : IDL.Nat64,
+  });
+  const RefreshSchedule = IDL.Record({
+    'armed_at' : IDL.Opt(IDL.Nat64),
+    'mode' : RefreshMode,
+    'interval_secs' : IDL.Nat64,
+    'recent_runs' : IDL.Vec(RefreshRun),
+    'next_run_at' : IDL.Opt(IDL.Nat64),
+    'paused' : IDL.Bool,
+  });
+  const RecipientChange = IDL.Record({
+    'changed_at' : IDL.Nat64,
+    'recipient' : IDL.Opt(Account),
+  });
+  const SelfRefreshConfig = IDL.Record({
+    'window_secs' : IDL.Nat64,
+    'enabled' : IDL.Bool,
+    'max_per_window' : IDL.Nat32,
+    'cooldown_secs' : IDL.Nat64,
+  });
+  const SnapshotRetention = IDL.Record({
+    'max_age_secs' : IDL.Opt(IDL.Nat64),
+    'max_snapshots' : IDL.Opt(IDL.Nat32),
+  });
+  const WeightedHolding = IDL.Record({
+    'collection' : IDL.Principal,
+    'average_milli' : IDL.Nat64,
+  });
+  const WeightedHolder = IDL.Record({
+    'principal' : IDL.Principal,
+    'holdings' : IDL.Vec(WeightedHolding),
+    'total_average_milli' : IDL.Nat64,
+  });
+  const WeightedHoldings = IDL.Record({
+    'window_start' : IDL.Nat64,
+    'snapshots_used' : IDL.Nat32,
+    'holders' : IDL.Vec(WeightedHolder),
+    'window_end' : IDL.Nat64,
+    'covered_from' : IDL.Opt(IDL.Nat64),
+  });
+  const SnapshotSource = IDL.Variant({
+    'CsvImport' : IDL.Null,
+    'Registry' : IDL.Null,
+    'PerHolder' : IDL.Null,
+  });
+  const SnapshotSummary = IDL.Record({
+    'source' : SnapshotSource,
+    'collections' : IDL.Vec(IDL.Principal),
+    'holders' : IDL.Nat64,
+    'changes' : IDL.Opt(IDL.Nat64),
+    'total_tokens' : IDL.Nat64,
+    'taken_at' : IDL.Nat64,
+    'snapshot_id' : IDL.Nat64,
+  });
+  const CsvImportReport = IDL.Record({
+    'gg' : CsvFileReport,
+    'daku' : CsvFileReport,
+    'holders' : IDL.Nat64,
+  });
+  const CollectionRefreshReport = IDL.Record({
+    'owners_unresolved' : IDL.Nat64,
+    'collection' : IDL.Principal,
+    'error' : IDL.Opt(IDL.Text),
+    'unresolved_owners' : IDL.Vec(IDL.Text),
+    'tokens_unresolved' : IDL.Nat64,
+    'tokens_attributed' : IDL.Nat64,
+  });
+  const RegistryRefreshReport = IDL.Record({
+    'collections' : IDL.Vec(CollectionRefreshReport),
+    'tokens_unresolved' : IDL.Nat64,
+    'tokens_attributed' : IDL.Nat64,
+    'holders' : IDL.Nat64,
+  });
   return IDL.Service({
+    'add_admin' : IDL.Func(
+        [IDL.Principal],
+        [IDL.Variant({ 'Ok' : IDL.Null, 'Err' : WalletError })],
+        [],
+      ),
+    'add_exclusion' : IDL.Func(
+        [IDL.Principal, IDL.Text],
+        [IDL.Variant({ 'Ok' : IDL.Null, 'Err' : WalletError })],
+        [],
+      ),
+    'append_chunk' : IDL.Func(
+        [IDL.Nat64, IDL.Vec(IDL.Nat8)],
+        [IDL.Variant({ 'Ok' : ImportProgress, 'Err' : WalletError })],
+        [],
+      ),
+    'begin_import' : IDL.Func(
+        [IDL.Principal],
+        [IDL.Variant({ 'Ok' : IDL.Nat64, 'Err' : WalletError })],
+        [],
+      ),
+    'bulk_update_nft_counts' : IDL.Func(
+        [IDL.Vec(IDL.Principal)],
+        [
+          IDL.Variant({
+            'Ok' : IDL.Vec(IDL.Tuple(IDL.Principal, IDL.Nat64)),
+            'Err' : WalletError,
+          }),
+        ],
+        [],
+      ),
+    'cancel_refresh_job' : IDL.Func(
+        [],
+        [IDL.Variant({ 'Ok' : IDL.Opt(RefreshJob), 'Err' : WalletError })],
+        [],
+      ),
+    'cancel_wallet_link' : IDL.Func(
+        [IDL.Principal],
+        [IDL.Variant({ 'Ok' : IDL.Bool, 'Err' : WalletError })],
+        [],
+      ),
+    'clear_reward_recipient' : IDL.Func(
+        [],
+        [IDL.Variant({ 'Ok' : Account, 'Err' : WalletError })],
+        [],
+      ),
+    'commit_import' : IDL.Func(
+        [IDL.Nat64, IDL.Text, ImportMode],
+        [IDL.Variant({ 'Ok' : CollectionImportReport, 'Err' : WalletError })],
+        [],
+      ),
+    'compute_reward_plan' : IDL.Func([IDL.Nat64], [RewardPlan], ['query']),
+    'confirm_wallet_link' : IDL.Func(
+        [IDL.Principal],
+        [IDL.Variant({ 'Ok' : LinkedWallets, 'Err' : WalletError })],
+        [],
+      ),
+    'get_account_ids_of' : IDL.Func(
+        [IDL.Principal],
+        [IDL.Vec(IDL.Text)],
+        ['query'],
+      ),
+    'get_account_index_stats' : IDL.Func([], [AccountIndexStats], ['query']),
+    'get_admins' : IDL.Func([], [IDL.Vec(IDL.Principal)], ['query']),
     'get_all_holders' : IDL.Func(
         [],
         [IDL.Vec(IDL.Tuple(IDL.Principal, HolderInfo))],
@@ -21,10 +447,226 @@ export const idlFactory = ({ IDL }) => {
         [IDL.Vec(IDL.Tuple(IDL.Principal, NFTProgress))],
         ['query'],
       ),
+    'get_all_tokens' : IDL.Func(
+        [IDL.Text],
+        [IDL.Variant({ 'Ok' : GetAllTokensResponse, 'Err' : WalletError })],
+        [],
+      ),
     'get_balance' : IDL.Func([IDL.Principal], [IDL.Nat64], ['query']),
+    'get_collections' : IDL.Func([], [IDL.Vec(Collection)], ['query']),
+    'get_data_quality_report' : IDL.Func([], [DataQualityReport], ['query']),
+    'get_debug_info' : IDL.Func([], [IDL.Vec(IDL.Text)], ['query']),
+    'get_exclusion_config' : IDL.Func([], [ExclusionConfig], ['query']),
+    'get_exclusion_reason' : IDL.Func(
+        [IDL.Principal],
+        [IDL.Opt(ExclusionReason)],
+        ['query'],
+      ),
+    'get_exclusions' : IDL.Func(
+        [],
+        [IDL.Vec(IDL.Tuple(IDL.Principal, Exclusion))],
+        ['query'],
+      ),
+    'get_holder_at' : IDL.Func(
+        [IDL.Principal, IDL.Nat64],
+        [IDL.Opt(HolderAt)],
+        ['query'],
+      ),
+    'get_holders_page' : IDL.Func(
+        [IDL.Opt(IDL.Nat64), IDL.Opt(IDL.Principal), IDL.Nat32],
+        [IDL.Variant({ 'Ok' : HoldersPage, 'Err' : WalletError })],
+        ['query'],
+      ),
+    'get_holders_snapshot' : IDL.Func([], [HolderSnapshot], ['query']),
+    'get_linked_wallets' : IDL.Func(
+        [IDL.Principal],
+        [LinkedWallets],
+        ['query'],
+      ),
     'get_nft_count' : IDL.Func([IDL.Principal], [NFTProgress], ['query']),
-    'update_all_holders' : IDL.Func([], [IDL.Nat64], []),
-    'update_balance' : IDL.Func([IDL.Principal, IDL.Nat64], [IDL.Nat64], []),
+    'get_nft_registry' : IDL.Func(
+        [IDL.Text],
+        [IDL.Variant({ 'Ok' : IDL.Text, 'Err' : WalletError })],
+        [],
+      ),
+    'get_owner_of' : IDL.Func(
+        [IDL.Principal, IDL.Nat32],
+        [IDL.Opt(IDL.Principal)],
+        ['query'],
+      ),
+    'get_refresh_job' : IDL.Func([], [IDL.Opt(RefreshJob)], ['query']),
+    'get_refresh_schedule' : IDL.Func([], [RefreshSchedule], ['query']),
+    'get_reward_policy' : IDL.Func([], [RewardPolicy], ['query']),
+    'get_reward_recipient' : IDL.Func([IDL.Principal], [Account], ['query']),
+    'get_reward_recipient_history' : IDL.Func(
+        [IDL.Principal],
+        [IDL.Vec(RecipientChange)],
+        ['query'],
+      ),
+    'get_self_refresh_config' : IDL.Func([], [SelfRefreshConfig], ['query']),
+    'get_snapshot_retention' : IDL.Func([], [SnapshotRetention], ['query']),
+    'get_tokens_of' : IDL.Func(
+        [IDL.Principal, IDL.Principal],
+        [IDL.Vec(IDL.Nat32)],
+        ['query'],
+      ),
+    'get_total_holders' : IDL.Func([], [IDL.Nat64], ['query']),
+    'get_weighted_holdings' : IDL.Func(
+        [IDL.Nat64],
+        [IDL.Variant({ 'Ok' : WeightedHoldings, 'Err' : WalletError })],
+        ['query'],
+      ),
+    'import_collection_csv' : IDL.Func(
+        [IDL.Principal, IDL.Text, ImportMode],
+        [IDL.Variant({ 'Ok' : CollectionImportReport, 'Err' : WalletError })],
+        [],
+      ),
+    'is_admin' : IDL.Func([IDL.Principal], [IDL.Bool], ['query']),
+    'is_using_csv_data' : IDL.Func([], [IDL.Bool], ['query']),
+    'list_snapshots' : IDL.Func([], [IDL.Vec(SnapshotSummary)], ['query']),
+    'load_csv_data' : IDL.Func(
+        [IDL.Text, IDL.Text],
+        [IDL.Variant({ 'Ok' : CsvImportReport, 'Err' : WalletError })],
+        [],
+      ),
+    'load_test_csv_data' : IDL.Func(
+        [],
+        [IDL.Variant({ 'Ok' : CsvImportReport, 'Err' : WalletError })],
+        [],
+      ),
+    'pause_refresh' : IDL.Func(
+        [],
+        [IDL.Variant({ 'Ok' : RefreshSchedule, 'Err' : WalletError })],
+        [],
+      ),
+    'propose_wallet_link' : IDL.Func(
+        [IDL.Principal],
+        [IDL.Variant({ 'Ok' : LinkProposal, 'Err' : WalletError })],
+        [],
+      ),
+    'rebuild_account_index' : IDL.Func(
+        [],
+        [IDL.Variant({ 'Ok' : AccountIndexStats, 'Err' : WalletError })],
+        [],
+      ),
+    'refresh_holders_from_registry' : IDL.Func(
+        [],
+        [IDL.Variant({ 'Ok' : RegistryRefreshReport, 'Err' : WalletError })],
+        [],
+      ),
+    'refresh_my_holdings' : IDL.Func(
+        [],
+        [IDL.Variant({ 'Ok' : HolderInfo, 'Err' : WalletError })],
+        [],
+      ),
+    'remove_admin' : IDL.Func(
+        [IDL.Principal],
+        [IDL.Variant({ 'Ok' : IDL.Bool, 'Err' : WalletError })],
+        [],
+      ),
+    'remove_collection' : IDL.Func(
+        [IDL.Principal],
+        [IDL.Variant({ 'Ok' : IDL.Bool, 'Err' : WalletError })],
+        [],
+      ),
+    'remove_exclusion' : IDL.Func(
+        [IDL.Principal],
+        [IDL.Variant({ 'Ok' : IDL.Bool, 'Err' : WalletError })],
+        [],
+      ),
+    'resolve_account_id' : IDL.Func(
+        [IDL.Text],
+        [IDL.Opt(IDL.Principal)],
+        ['query'],
+      ),
+    'resume_refresh_job' : IDL.Func(
+        [],
+        [IDL.Variant({ 'Ok' : RefreshJob, 'Err' : WalletError })],
+        [],
+      ),
+    'set_auto_exclude_canisters' : IDL.Func(
+        [IDL.Bool],
+        [IDL.Variant({ 'Ok' : ExclusionConfig, 'Err' : WalletError })],
+        [],
+      ),
+    'set_collection_enabled' : IDL.Func(
+        [IDL.Principal, IDL.Bool],
+        [IDL.Variant({ 'Ok' : Collection, 'Err' : WalletError })],
+        [],
+      ),
+    'set_refresh_interval' : IDL.Func(
+        [IDL.Nat64, IDL.Opt(RefreshMode)],
+        [IDL.Variant({ 'Ok' : RefreshSchedule, 'Err' : WalletError })],
+        [],
+      ),
+    'set_reward_policy' : IDL.Func(
+        [RewardPolicy],
+        [IDL.Variant({ 'Ok' : RewardPolicy, 'Err' : WalletError })],
+        [],
+      ),
+    'set_reward_recipient' : IDL.Func(
+        [Account],
+        [IDL.Variant({ 'Ok' : Account, 'Err' : WalletError })],
+        [],
+      ),
+    'set_self_refresh_config' : IDL.Func(
+        [SelfRefreshConfig],
+        [IDL.Variant({ 'Ok' : SelfRefreshConfig, 'Err' : WalletError })],
+        [],
+      ),
+    'set_snapshot_retention' : IDL.Func(
+        [IDL.Opt(IDL.Nat32), IDL.Opt(IDL.Nat64)],
+        [IDL.Variant({ 'Ok' : SnapshotRetention, 'Err' : WalletError })],
+        [],
+      ),
+    'set_subaccount_range' : IDL.Func(
+        [IDL.Nat32],
+        [IDL.Variant({ 'Ok' : AccountIndexStats, 'Err' : WalletError })],
+        [],
+      ),
+    'set_verified_nft_counts' : IDL.Func(
+        [IDL.Principal, IDL.Nat64, IDL.Nat64],
+        [IDL.Variant({ 'Ok' : HolderInfo, 'Err' : WalletError })],
+        [],
+      ),
+    'test_direct_canister_calls' : IDL.Func(
+        [],
+        [IDL.Variant({ 'Ok' : IDL.Vec(IDL.Text), 'Err' : WalletError })],
+        [],
+      ),
+    'test_ext_query' : IDL.Func(
+        [IDL.Text, IDL.Text],
+        [IDL.Variant({ 'Ok' : IDL.Vec(IDL.Text), 'Err' : WalletError })],
+        [],
+      ),
+    'unlink_wallet' : IDL.Func(
+        [IDL.Principal],
+        [IDL.Variant({ 'Ok' : IDL.Bool, 'Err' : WalletError })],
+        [],
+      ),
+    'update_all_holders' : IDL.Func(
+        [],
+        [IDL.Variant({ 'Ok' : IDL.Nat64, 'Err' : WalletError })],
+        [],
+      ),
+    'update_balance' : IDL.Func(
+        [IDL.Principal, IDL.Nat64],
+        [IDL.Variant({ 'Ok' : IDL.Nat64, 'Err' : WalletError })],
+        [],
+      ),
+    'update_nft_count' : IDL.Func(
+        [IDL.Principal],
+        [IDL.Variant({ 'Ok' : IDL.Nat64, 'Err' : WalletError })],
+        [],
+      ),
+    'upsert_collection' : IDL.Func(
+        [Collection],
+        [IDL.Variant({ 'Ok' : IDL.Null, 'Err' : WalletError })],
+        [],
+      ),
   });
 };
-export const init = ({ IDL }) => { return []; };
+export const init = ({ IDL }) => {
+  const InitArgs = IDL.Record({ 'admins' : IDL.Vec(IDL.Principal) });
+  return [IDL.Opt(InitArgs)];
+};
\ No newline at end of file
@@ -49,6 +49,8 @@ const agent = new HttpAgent({
 // agent.fetchRootKey();
 
 // Define the interface for the wallet canister
+// Only the fields this tool reads are declared; Candid ignores the rest of each record.
+// See src/declarations/wallet_rust for the full interface.
 const walletIdl = ({ IDL }) => {
   const HolderInfo = IDL.Record({
     'daku_count': IDL.Nat64,
@@ -56,16 +58,48 @@ const walletIdl = ({ IDL }) => {
     'total_count': IDL.Nat64,
     'last_updated': IDL.Nat64,
   });
+  const RejectionCode = IDL.Variant({
+    'NoError': IDL.Null,
+    'SysFatal': IDL.Null,
+    'SysTransient': IDL.Null,
+    'DestinationInvalid': IDL.Null,
+    'CanisterReject': IDL.Null,
+    'CanisterError': IDL.Null,
+    'Unknown': IDL.Null,
+  });
+  const WalletError = IDL.Variant({
+    'Unauthorized': IDL.Null,
+    'InvalidPrincipal': IDL.Text,
+    'CanisterUnreachable': IDL.Record({ 'code': RejectionCode, 'msg': IDL.Text }),
+    'DecodeFailed': IDL.Text,
+    'CsvParse': IDL.Record({ 'line': IDL.Nat64, 'reason': IDL.Text }),
+    'CollectionNotFound': IDL.Principal,
+    'InvalidArgument': IDL.Text,
+    'RefreshInProgress': IDL.Null,
+    'SnapshotChanged': IDL.Record({ 'requested': IDL.Nat64, 'current': IDL.Nat64 }),
+    'CooldownActive': IDL.Record({ 'retry_after_secs': IDL.Nat64 }),
+    'RefreshBudgetExhausted': IDL.Record({ 'retry_after_secs': IDL.Nat64 }),
+  });
+  const Result = (ok) => IDL.Variant({ 'Ok': ok, 'Err': WalletError });
+  const CsvImportReport = IDL.Record({ 'holders': IDL.Nat64 });
   return IDL.Service({
-    'load_csv_data': IDL.Func([IDL.Text, IDL.Text], [IDL.Bool], []),
-    'update_all_holders': IDL.Func([], [IDL.Nat64], []),
+    'load_csv_data': IDL.Func([IDL.Text, IDL.Text], [Result(CsvImportReport)], []),
+    'update_all_holders': IDL.Func([], [Result(IDL.Nat64)], []),
     'get_all_holders': IDL.Func([], [IDL.Vec(IDL.Tuple(IDL.Principal, HolderInfo))], ['query']),
-    'test_direct_canister_calls': IDL.Func([], [IDL.Vec(IDL.Text)], []),
-    'test_ext_query': IDL.Func([IDL.Text, IDL.Text], [IDL.Vec(IDL.Text)], []),
+    'test_direct_canister_calls': IDL.Func([], [Result(IDL.Vec(IDL.Text))], []),
+    'test_ext_query': IDL.Func([IDL.Text, IDL.Text], [Result(IDL.Vec(IDL.Text))], []),
     'is_using_csv_data': IDL.Func([], [IDL.Bool], ['query']),
   });
 };
 
+// Return the Ok value of a wallet canister Result, or throw its WalletError
+function unwrap(result, method) {
+  if ('Err' in result) {
+    throw new Error(`${method} failed: ${JSON.stringify(result.Err, (_, value) => typeof value === 'bigint' ? value.toString() : value)}`);
+  }
+  return result.Ok;
+}
+
 // Define the interface for the payout canister
 const payoutIdl = ({ IDL }) => {
   return IDL.Service({
@@ -116,7 +150,7 @@ async function getPayoutStats() {
 async function testCanisterConnections() {
   try {
     console.log('Testing direct canister connections...');
-    const testResults = await walletActor.test_direct_canister_calls();
+    const testResults = unwrap(await walletActor.test_direct_canister_calls(), 'test_direct_canister_calls');
     
     console.log('\nCanister Connection Test Results:');
     testResults.forEach(line => console.log(`  ${line}`));
@@ -125,14 +159,14 @@ async function testCanisterConnections() {
     
     // Test GG Album canister specifically
     console.log('\nTesting connection to GG Album Release canister...');
-    const ggTestResults = await walletActor.test_ext_query(GG_ALBUM_CANISTER_ID, testPrincipal);
+    const ggTestResults = unwrap(await walletActor.test_ext_query(GG_ALBUM_CANISTER_ID, testPrincipal), 'test_ext_query');
     
     console.log('\nGG Album Canister Test Results:');
     ggTestResults.forEach(line => console.log(`  ${line}`));
     
     // Test Daku Motoko canister specifically
     console.log('\nTesting connection to Daku Motoko canister...');
-    const dakuTestResults = await walletActor.test_ext_query(DAKU_MOTOKO_CANISTER_ID, testPrincipal);
+    const dakuTestResults = unwrap(await walletActor.test_ext_query(DAKU_MOTOKO_CANISTER_ID, testPrincipal), 'test_ext_query');
     
     console.log('\nDaku Motoko Canister Test Results:');
     dakuTestResults.forEach(line => console.log(`  ${line}`));
@@ -153,8 +187,8 @@ async function fetchFromCanisters() {
     if (isUsingCsvData) {
       console.log('System is currently using CSV data. Loading empty CSV data to disable CSV mode...');
       // Load empty CSV data to disable CSV mode
-      await walletActor.load_csv_data("accountIdentifier,principal,tokenIds,numberOfTokens", 
-                                     "accountIdentifier,principal,tokenIds,numberOfTokens");
+      unwrap(await walletActor.load_csv_data("accountIdentifier,principal,tokenIds,numberOfTokens",
+                                            "accountIdentifier,principal,tokenIds,numberOfTokens"), 'load_csv_data');
       console.log('Empty CSV data loaded to disable CSV mode.');
     }
     
@@ -163,9 +197,10 @@ async function fetchFromCanisters() {
     
     // Update all holders from NFT canisters
     console.log('Updating all holders from NFT canisters...');
-    const totalHolders = await walletActor.update_all_holders();
+    const totalHolders = unwrap(await walletActor.update_all_holders(), 'update_all_holders');
     
-    console.log(`All holders updated. Total holders: ${totalHolders}`);
+    // Outside CSV mode this only queues a batched refresh job; see get_refresh_job
+    console.log(`Holder refresh started. Total holders: ${totalHolders}`);
     
     // Get initial payout stats
     console.log('Getting current payout stats...');
@@ -0,0 +1,64 @@
+use candid::{CandidType, Principal};
+use ic_cdk::api::time;
+use ic_stable_structures::StableBTreeMap;
+use serde::Deserialize;
+use std::cell::RefCell;
+
+use crate::errors::WalletError;
+use crate::state::{self, Memory, StablePrincipal, ADMINS_MEMORY_ID};
+
+// Arguments accepted by `init` and `post_upgrade`
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct InitArgs {
+    pub admins: Vec<Principal>,
+}
+
+thread_local! {
+    // Admin principal -> timestamp it was granted access
+    static ADMINS: RefCell<StableBTreeMap<StablePrincipal, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(state::memory(ADMINS_MEMORY_ID)));
+}
+
+// Register every admin passed in through init/upgrade arguments
+pub fn apply_init_args(args: Option<InitArgs>) {
+    if let Some(args) = args {
+        for admin in args.admins {
+            insert_admin(admin);
+        }
+    }
+}
+
+// Controllers are always treated as admins so the canister can't lock itself out
+pub fn is_admin(principal: &Principal) -> bool {
+    if *principal == Principal::anonymous() {
+        return false;
+    }
+
+    ADMINS.with(|admins| admins.borrow().contains_key(&StablePrincipal(*principal)))
+        || ic_cdk::api::is_controller(principal)
+}
+
+// Guard for mutating endpoints
+pub fn require_admin() -> Result<(), WalletError> {
+    let caller = ic_cdk::caller();
+    if is_admin(&caller) {
+        Ok(())
+    } else {
+        ic_cdk::print(format!("Rejected admin call from {}", caller));
+        Err(WalletError::Unauthorized)
+    }
+}
+
+pub fn insert_admin(principal: Principal) {
+    ADMINS.with(|admins| {
+        admins.borrow_mut().insert(StablePrincipal(principal), time());
+    });
+}
+
+pub fn delete_admin(principal: &Principal) -> bool {
+    ADMINS.with(|admins| admins.borrow_mut().remove(&StablePrincipal(*principal)).is_some())
+}
+
+pub fn list_admins() -> Vec<Principal> {
+    ADMINS.with(|admins| admins.borrow().iter().map(|(k, _)| k.0).collect())
+}
@@ -0,0 +1,242 @@
+use candid::{CandidType, Principal};
+use ic_stable_structures::{StableBTreeMap, StableCell};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::time::Duration;
+
+use crate::ext::tokens::{principal_to_account_id_hex, subaccount_from_index};
+use crate::state::{self, Memory, ACCOUNT_INDEX_CONFIG_MEMORY_ID, ACCOUNT_INDEX_MEMORY_ID};
+
+// Upper bound on derived subaccounts per principal to keep a rebuild inside one message
+pub const MAX_SUBACCOUNT_RANGE: u32 = 64;
+
+// Principals re-derived per message during a rebuild. Each costs up to
+// MAX_SUBACCOUNT_RANGE + 1 account-id hashes.
+pub const REBUILD_BATCH_SIZE: usize = 100;
+
+// Principal (and subaccount number) behind an indexed AccountIdentifier
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AccountOwner {
+    pub principal: Principal,
+    // None when the binding came from an import rather than our own derivation
+    pub subaccount_index: Option<u32>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RebuildProgress {
+    // Last principal re-derived; the next batch starts after it
+    pub cursor: Option<Principal>,
+    // Range the index was derived with before, so ids above the new range can be dropped
+    pub previous_range: u32,
+    pub processed: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Default, Debug)]
+pub struct AccountIndexConfig {
+    // Subaccounts 1..=subaccount_range are derived in addition to the default account
+    pub subaccount_range: u32,
+    pub last_unresolved: u64,
+    pub last_rebuilt: u64,
+    // Set while a rebuild is working through the known principals
+    pub rebuild: Option<RebuildProgress>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct AccountIndexStats {
+    pub indexed_accounts: u64,
+    pub subaccount_range: u32,
+    pub last_unresolved: u64,
+    pub last_rebuilt: u64,
+    // Principals re-derived so far while a rebuild is running
+    pub rebuild_processed: Option<u64>,
+}
+
+state::impl_candid_storable!(AccountOwner, 64);
+state::impl_candid_storable!(AccountIndexConfig, 256);
+
+thread_local! {
+    // Raw 32-byte AccountIdentifier (checksum included) -> owner
+    static ACCOUNT_INDEX: RefCell<StableBTreeMap<[u8; 32], AccountOwner, Memory>> =
+        RefCell::new(StableBTreeMap::init(state::memory(ACCOUNT_INDEX_MEMORY_ID)));
+
+    static CONFIG: RefCell<StableCell<AccountIndexConfig, Memory>> = RefCell::new(
+        StableCell::init(state::memory(ACCOUNT_INDEX_CONFIG_MEMORY_ID), AccountIndexConfig::default())
+            .expect("Failed to init account index config")
+    );
+}
+
+pub fn get_config() -> AccountIndexConfig {
+    CONFIG.with(|config| config.borrow().get().clone())
+}
+
+fn update_config<F: FnOnce(&mut AccountIndexConfig)>(f: F) {
+    CONFIG.with(|config| {
+        let mut cell = config.borrow_mut();
+        let mut value = cell.get().clone();
+        f(&mut value);
+        cell.set(value).expect("Failed to write account index config");
+    });
+}
+
+// Parse a textual AccountIdentifier into its raw bytes
+fn parse_account_id(account_id: &str) -> Option<[u8; 32]> {
+    let bytes = hex::decode(account_id.trim().to_lowercase()).ok()?;
+    bytes.try_into().ok()
+}
+
+fn insert(account_id: &str, owner: AccountOwner) {
+    if let Some(key) = parse_account_id(account_id) {
+        ACCOUNT_INDEX.with(|index| {
+            index.borrow_mut().insert(key, owner);
+        });
+    }
+}
+
+// Derive and store the default account plus the configured subaccount range for a principal
+pub fn index_principal(principal: &Principal) {
+    let range = get_config().subaccount_range;
+    insert(&principal_to_account_id_hex(principal, None), AccountOwner {
+        principal: *principal,
+        subaccount_index: Some(0),
+    });
+    for i in 1..=range {
+        insert(&principal_to_account_id_hex(principal, Some(subaccount_from_index(i))), AccountOwner {
+            principal: *principal,
+            subaccount_index: Some(i),
+        });
+    }
+}
+
+pub fn index_principals<'a, I: IntoIterator<Item = &'a Principal>>(principals: I) {
+    for principal in principals {
+        index_principal(principal);
+    }
+}
+
+// Record an explicit accountIdentifier -> principal pair, e.g. from a CSV export
+pub fn bind_account(account_id: &str, principal: Principal) {
+    insert(account_id, AccountOwner { principal, subaccount_index: None });
+}
+
+// Re-derive every known principal with `subaccount_range`, REBUILD_BATCH_SIZE principals
+// per message. Derived ids are deterministic, so entries of principals we no longer track
+// stay correct and are left in place.
+pub fn start_rebuild(subaccount_range: u32) {
+    let mut already_running = false;
+    update_config(|config| {
+        // A rebuild started over another one still has to drop ids up to the older range
+        let previous_range = match &config.rebuild {
+            Some(progress) => progress.previous_range.max(config.subaccount_range),
+            None => config.subaccount_range,
+        };
+        already_running = config.rebuild.is_some();
+        config.subaccount_range = subaccount_range.min(MAX_SUBACCOUNT_RANGE);
+        config.rebuild = Some(RebuildProgress { cursor: None, previous_range, processed: 0 });
+    });
+    // The running batch chain picks up the reset cursor
+    if !already_running {
+        schedule_rebuild_batch();
+    }
+}
+
+// Called from post_upgrade: a pending batch timer was dropped with the old module
+pub fn resume_after_upgrade() {
+    if get_config().rebuild.is_some() {
+        schedule_rebuild_batch();
+    }
+}
+
+fn schedule_rebuild_batch() {
+    ic_cdk_timers::set_timer(Duration::ZERO, run_rebuild_batch);
+}
+
+fn run_rebuild_batch() {
+    let config = get_config();
+    let Some(progress) = config.rebuild else {
+        return;
+    };
+    let principals = crate::known_principals_after(progress.cursor, REBUILD_BATCH_SIZE);
+    if principals.is_empty() {
+        update_config(|config| {
+            config.rebuild = None;
+            config.last_rebuilt = ic_cdk::api::time();
+        });
+        ic_cdk::print(format!("Account index rebuilt for {} principals", progress.processed));
+        return;
+    }
+
+    for principal in &principals {
+        index_principal(principal);
+        for i in config.subaccount_range + 1..=progress.previous_range {
+            remove(&principal_to_account_id_hex(principal, Some(subaccount_from_index(i))));
+        }
+    }
+    update_config(|config| {
+        if let Some(progress) = config.rebuild.as_mut() {
+            progress.cursor = principals.last().copied();
+            progress.processed += principals.len() as u64;
+        }
+    });
+    schedule_rebuild_batch();
+}
+
+fn remove(account_id: &str) {
+    if let Some(key) = parse_account_id(account_id) {
+        ACCOUNT_INDEX.with(|index| {
+            index.borrow_mut().remove(&key);
+        });
+    }
+}
+
+pub fn lookup(account_id: &str) -> Option<AccountOwner> {
+    let key = parse_account_id(account_id)?;
+    ACCOUNT_INDEX.with(|index| index.borrow().get(&key))
+}
+
+// Map a getRegistry owner back to a principal. Some canisters return principal text,
+// EXT registries return hex AccountIdentifiers.
+pub fn resolve_owner(owner: &str) -> Option<Principal> {
+    if let Some(found) = lookup(owner) {
+        return Some(found.principal);
+    }
+    Principal::from_text(owner).ok()
+}
+
+// All textual AccountIdentifiers that resolve to `principal`
+pub fn account_ids_of(principal: &Principal) -> Vec<String> {
+    ACCOUNT_INDEX.with(|index| {
+        index.borrow().iter()
+            .filter(|(_, owner)| owner.principal == *principal)
+            .map(|(key, _)| hex::encode(key))
+            .collect()
+    })
+}
+
+// Resolve a whole registry, returning attributed entries and the number of unknown owners
+pub fn resolve_registry<T: Copy>(records: Vec<(T, String)>) -> (Vec<(T, Principal)>, u64) {
+    let mut resolved = Vec::with_capacity(records.len());
+    let mut unresolved = 0u64;
+    for (index, owner) in records {
+        match resolve_owner(&owner) {
+            Some(principal) => resolved.push((index, principal)),
+            None => unresolved += 1,
+        }
+    }
+    set_last_unresolved(unresolved);
+    (resolved, unresolved)
+}
+
+pub fn set_last_unresolved(unresolved: u64) {
+    update_config(|config| config.last_unresolved = unresolved);
+}
+
+pub fn stats() -> AccountIndexStats {
+    let config = get_config();
+    AccountIndexStats {
+        indexed_accounts: ACCOUNT_INDEX.with(|index| index.borrow().len()),
+        subaccount_range: config.subaccount_range,
+        last_unresolved: config.last_unresolved,
+        last_rebuilt: config.last_rebuilt,
+        rebuild_processed: config.rebuild.map(|progress| progress.processed),
+    }
+}
@@ -0,0 +1,169 @@
+use candid::{CandidType, Principal};
+use ic_stable_structures::StableBTreeMap;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+
+use crate::errors::WalletError;
+use crate::state::{self, Memory, StablePrincipal, COLLECTIONS_MEMORY_ID};
+
+// Canister ids of the collections we launched with; also drive the legacy HolderInfo fields
+pub const DAKU_MOTOKO_CANISTER: &str = "erfen-7aaaa-aaaap-ahniq-cai";
+pub const GG_ALBUM_CANISTER: &str = "v2ekv-yyaaa-aaaag-qjw2q-cai";
+
+// 1.0x reward weight expressed in basis points
+pub const DEFAULT_REWARD_WEIGHT_BPS: u32 = 10_000;
+
+// Which `tokens` interface the collection canister exposes
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CollectionStandard {
+    // Generic EXT canister, probed with every argument encoding we know
+    Ext,
+    // `tokens(text) -> (vec principal)` as served by Daku Motoko
+    DakuTokens,
+    // `tokens(text) -> (vec record { nat; principal })` as served by GG Album
+    AlbumTokens,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Collection {
+    pub canister_id: Principal,
+    pub name: String,
+    pub standard: CollectionStandard,
+    pub reward_weight_bps: u32,
+    pub enabled: bool,
+}
+
+// Reject messages can be long; keep stored errors small enough for HolderInfo's bound.
+// In bytes, since a cap in chars could still be four times as long once encoded.
+const MAX_STALE_ERROR_LEN: usize = 160;
+
+// Longest collection name, in bytes; well inside Collection's 512-byte bound
+const MAX_COLLECTION_NAME_LEN: usize = 128;
+
+// HolderInfo has one entry per collection in a 4096-byte slot. An entry with a full stale
+// error encodes to about 220 bytes and the rest of the record to about 400, so 16 fit.
+pub const MAX_COLLECTIONS: u64 = 16;
+
+// Number of tokens a holder owns in one collection
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CollectionHolding {
+    pub collection: Principal,
+    pub count: u64,
+    // Set when the collection couldn't be read and `count` is the last known value
+    pub stale: Option<StaleHolding>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct StaleHolding {
+    pub error: String,
+    pub failed_at: u64,
+    // When `count` was last read successfully (age = now - confirmed_at); None if it never was
+    pub confirmed_at: Option<u64>,
+}
+
+impl CollectionHolding {
+    pub fn new(collection: Principal, count: u64) -> Self {
+        CollectionHolding { collection, count, stale: None }
+    }
+}
+
+impl StaleHolding {
+    pub fn new(error: &str, failed_at: u64, confirmed_at: Option<u64>) -> Self {
+        StaleHolding {
+            error: truncate_error(error),
+            failed_at,
+            confirmed_at,
+        }
+    }
+}
+
+fn truncate_error(error: &str) -> String {
+    let mut end = error.len().min(MAX_STALE_ERROR_LEN);
+    while !error.is_char_boundary(end) {
+        end -= 1;
+    }
+    error[..end].to_string()
+}
+
+state::impl_candid_storable!(Collection, 512);
+
+thread_local! {
+    static COLLECTIONS: RefCell<StableBTreeMap<StablePrincipal, Collection, Memory>> =
+        RefCell::new(StableBTreeMap::init(state::memory(COLLECTIONS_MEMORY_ID)));
+}
+
+pub fn daku_canister() -> Principal {
+    Principal::from_text(DAKU_MOTOKO_CANISTER).expect("Invalid Daku canister id")
+}
+
+pub fn gg_canister() -> Principal {
+    Principal::from_text(GG_ALBUM_CANISTER).expect("Invalid GG Album canister id")
+}
+
+fn default_collections() -> Vec<Collection> {
+    vec![
+        Collection {
+            canister_id: daku_canister(),
+            name: "Daku Motoko".to_string(),
+            standard: CollectionStandard::DakuTokens,
+            reward_weight_bps: DEFAULT_REWARD_WEIGHT_BPS,
+            enabled: true,
+        },
+        Collection {
+            canister_id: gg_canister(),
+            name: "GG Album Release".to_string(),
+            standard: CollectionStandard::AlbumTokens,
+            reward_weight_bps: DEFAULT_REWARD_WEIGHT_BPS,
+            enabled: true,
+        },
+    ]
+}
+
+// Register the launch collections if the registry has never been populated
+pub fn seed_default_collections() {
+    COLLECTIONS.with(|collections| {
+        let mut collections = collections.borrow_mut();
+        if collections.is_empty() {
+            for collection in default_collections() {
+                collections.insert(StablePrincipal(collection.canister_id), collection);
+            }
+        }
+    });
+}
+
+pub fn get_collection(canister_id: &Principal) -> Option<Collection> {
+    COLLECTIONS.with(|collections| collections.borrow().get(&StablePrincipal(*canister_id)))
+}
+
+pub fn list_collections() -> Vec<Collection> {
+    COLLECTIONS.with(|collections| collections.borrow().iter().map(|(_, v)| v).collect())
+}
+
+pub fn enabled_collections() -> Vec<Collection> {
+    list_collections().into_iter().filter(|c| c.enabled).collect()
+}
+
+// Save a collection; the name and the number of collections are bounded so the records
+// that hold them can't outgrow their stable-memory slots
+pub fn upsert_collection(collection: Collection) -> Result<(), WalletError> {
+    if collection.name.len() > MAX_COLLECTION_NAME_LEN {
+        return Err(WalletError::InvalidArgument(format!(
+            "collection name is longer than {} bytes", MAX_COLLECTION_NAME_LEN
+        )));
+    }
+    COLLECTIONS.with(|collections| {
+        let mut collections = collections.borrow_mut();
+        let key = StablePrincipal(collection.canister_id);
+        if !collections.contains_key(&key) && collections.len() >= MAX_COLLECTIONS {
+            return Err(WalletError::InvalidArgument(format!(
+                "at most {} collections can be registered", MAX_COLLECTIONS
+            )));
+        }
+        collections.insert(key, collection);
+        Ok(())
+    })
+}
+
+pub fn delete_collection(canister_id: &Principal) -> bool {
+    COLLECTIONS.with(|collections| collections.borrow_mut().remove(&StablePrincipal(*canister_id)).is_some())
+}
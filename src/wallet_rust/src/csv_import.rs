@@ -0,0 +1,234 @@
+use candid::{CandidType, Principal};
+use ic_cdk::api::time;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::data_quality::CollectionQuality;
+use crate::csv_loader::{CsvFileReport, HolderCsvAccumulator, ParsedHolderCsv, MAX_REPORTED_ROWS};
+use crate::csv_reader::CsvReader;
+use crate::errors::WalletError;
+
+// Uploads left unfinished for this long are dropped when the next one begins
+const SESSION_TTL_NS: u64 = 60 * 60 * 1_000_000_000;
+const MAX_OPEN_SESSIONS: usize = 4;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ImportProgress {
+    pub session_id: u64,
+    pub collection: Principal,
+    pub bytes_received: u64,
+    pub chunks: u64,
+    pub rows_read: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportMode {
+    // The file is the collection's complete holder list; holders missing from it drop to zero
+    Replace,
+    // Only the holders in the file are updated; everyone else keeps their count
+    Merge,
+    // Report what Replace would change without writing anything
+    DryRun,
+}
+
+// One holder's count for the imported collection before and after the import
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct HolderChange {
+    pub principal: Principal,
+    pub before: u64,
+    pub after: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Default, Debug)]
+pub struct ImportDiff {
+    pub added_holders: u64,
+    pub removed_holders: u64,
+    pub changed_holders: u64,
+    pub unchanged_holders: u64,
+    // Individual changes, capped like the row lists in CsvFileReport
+    pub changes: Vec<HolderChange>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CollectionImportReport {
+    pub collection: Principal,
+    pub mode: ImportMode,
+    pub sha256: String,
+    pub file: CsvFileReport,
+    pub diff: ImportDiff,
+    // Conflicts and supply checks for the collection as it looks after the import
+    pub quality: CollectionQuality,
+    // Holders in the snapshot after the import (or that it would have, for DryRun)
+    pub holders: u64,
+}
+
+// Compare the collection's counts before and after an import, keyed by holder
+pub fn diff_counts(before: &HashMap<Principal, u64>, after: &HashMap<Principal, u64>) -> ImportDiff {
+    let mut principals: Vec<Principal> = before.keys().chain(after.keys()).copied().collect();
+    principals.sort();
+    principals.dedup();
+
+    let mut diff = ImportDiff::default();
+    for principal in principals {
+        let old = before.get(&principal).copied().unwrap_or(0);
+        let new = after.get(&principal).copied().unwrap_or(0);
+        match (old, new) {
+            _ if old == new => {
+                diff.unchanged_holders += 1;
+                continue;
+            },
+            (0, _) => diff.added_holders += 1,
+            (_, 0) => diff.removed_holders += 1,
+            _ => diff.changed_holders += 1,
+        }
+        if diff.changes.len() < MAX_REPORTED_ROWS {
+            diff.changes.push(HolderChange { principal, before: old, after: new });
+        }
+    }
+    diff
+}
+
+// An upload in progress. Chunks are hashed and parsed as they arrive, so the raw file is
+// never kept. Sessions live on the heap and are lost on upgrade; the upload then restarts.
+struct ImportSession {
+    collection: Principal,
+    started_at: u64,
+    bytes_received: u64,
+    chunks: u64,
+    hasher: Sha256,
+    // Trailing bytes of an incomplete UTF-8 sequence, prepended to the next chunk
+    utf8_tail: Vec<u8>,
+    reader: CsvReader,
+    rows: HolderCsvAccumulator,
+}
+
+impl ImportSession {
+    fn progress(&self, session_id: u64) -> ImportProgress {
+        ImportProgress {
+            session_id,
+            collection: self.collection,
+            bytes_received: self.bytes_received,
+            chunks: self.chunks,
+            rows_read: self.rows.rows_read(),
+        }
+    }
+}
+
+thread_local! {
+    static SESSIONS: RefCell<BTreeMap<u64, ImportSession>> = const { RefCell::new(BTreeMap::new()) };
+    static NEXT_SESSION_ID: RefCell<u64> = const { RefCell::new(1) };
+}
+
+pub fn begin(collection: Principal) -> Result<u64, WalletError> {
+    let now = time();
+    SESSIONS.with(|sessions| {
+        let mut sessions = sessions.borrow_mut();
+        sessions.retain(|_, session| now.saturating_sub(session.started_at) < SESSION_TTL_NS);
+        if sessions.len() >= MAX_OPEN_SESSIONS {
+            return Err(WalletError::InvalidArgument(format!(
+                "{} imports are already open; commit them or wait for them to expire", MAX_OPEN_SESSIONS
+            )));
+        }
+
+        let session_id = NEXT_SESSION_ID.with(|next| {
+            let mut next = next.borrow_mut();
+            let id = *next;
+            *next += 1;
+            id
+        });
+        sessions.insert(session_id, ImportSession {
+            collection,
+            started_at: now,
+            bytes_received: 0,
+            chunks: 0,
+            hasher: Sha256::new(),
+            utf8_tail: Vec::new(),
+            reader: CsvReader::default(),
+            rows: HolderCsvAccumulator::default(),
+        });
+        Ok(session_id)
+    })
+}
+
+fn unknown_session(session_id: u64) -> WalletError {
+    WalletError::InvalidArgument(format!("unknown or expired import session {}", session_id))
+}
+
+// Hash and parse the next piece of the file. A parse error aborts the session.
+pub fn append(session_id: u64, bytes: Vec<u8>) -> Result<ImportProgress, WalletError> {
+    SESSIONS.with(|sessions| {
+        let mut sessions = sessions.borrow_mut();
+        let session = sessions.get_mut(&session_id).ok_or_else(|| unknown_session(session_id))?;
+        let result = feed(session, &bytes).map(|_| session.progress(session_id));
+        if result.is_err() {
+            sessions.remove(&session_id);
+        }
+        result
+    })
+}
+
+fn feed(session: &mut ImportSession, bytes: &[u8]) -> Result<(), WalletError> {
+    session.hasher.update(bytes);
+    session.bytes_received += bytes.len() as u64;
+    session.chunks += 1;
+
+    let mut pending = std::mem::take(&mut session.utf8_tail);
+    pending.extend_from_slice(bytes);
+    let valid_up_to = match std::str::from_utf8(&pending) {
+        Ok(_) => pending.len(),
+        // error_len() == None means the input just ends mid-character
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        Err(e) => {
+            return Err(WalletError::DecodeFailed(format!(
+                "upload is not valid UTF-8 at byte {}", session.bytes_received - pending.len() as u64 + e.valid_up_to() as u64
+            )));
+        }
+    };
+    session.utf8_tail = pending.split_off(valid_up_to);
+    let text = std::str::from_utf8(&pending).expect("validated above");
+
+    for record in session.reader.feed(text)? {
+        session.rows.push(&record)?;
+    }
+    Ok(())
+}
+
+// Check the whole file against `sha256` (hex) and return what was parsed. The session is
+// closed unless `keep_open` is set, which lets a dry run be followed by the real import.
+// A hash mismatch leaves the session open so the commit can be retried with the right hash.
+pub fn commit(session_id: u64, sha256: &str, keep_open: bool) -> Result<(Principal, String, ParsedHolderCsv), WalletError> {
+    let (session, computed) = SESSIONS.with(|sessions| -> Result<(ImportSession, String), WalletError> {
+        let mut sessions = sessions.borrow_mut();
+        let session = sessions.get(&session_id).ok_or_else(|| unknown_session(session_id))?;
+        let computed = hex::encode(session.hasher.clone().finalize());
+        if !computed.eq_ignore_ascii_case(sha256.trim()) {
+            return Err(WalletError::InvalidArgument(format!(
+                "sha256 mismatch: expected {}, uploaded data hashes to {}", sha256.trim(), computed
+            )));
+        }
+        let session = if keep_open {
+            ImportSession {
+                hasher: session.hasher.clone(),
+                utf8_tail: session.utf8_tail.clone(),
+                reader: session.reader.clone(),
+                rows: session.rows.clone(),
+                ..*session
+            }
+        } else {
+            sessions.remove(&session_id).expect("session was just found")
+        };
+        Ok((session, computed))
+    })?;
+
+    if !session.utf8_tail.is_empty() {
+        return Err(WalletError::DecodeFailed("upload ends in the middle of a UTF-8 character".to_string()));
+    }
+
+    let mut rows = session.rows;
+    for record in session.reader.finish()? {
+        rows.push(&record)?;
+    }
+    Ok((session.collection, computed, rows.finish()?))
+}
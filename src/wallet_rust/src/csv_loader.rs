@@ -3,12 +3,99 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use ic_cdk::api::time;
 
+use crate::collections::{self, CollectionHolding, StaleHolding};
+use crate::csv_reader::{self, CsvRecord};
+use crate::errors::WalletError;
+use crate::exclusions::ExclusionReason;
+use crate::nft_registry_interface::TokenIndex;
+use crate::recipients::Account;
+use crate::rewards::{self, SetBonus};
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Default, Debug)]
 pub struct HolderInfo {
+    // Legacy fields kept for the Motoko payout canister
     pub daku_count: u64,
     pub gg_count: u64,
     pub total_count: u64,
     pub last_updated: u64,
+    // Counts for every registered collection (schema v2)
+    pub collections: Option<Vec<CollectionHolding>>,
+    // Set rule from the reward policy this holder qualifies for
+    pub set_bonus: Option<SetBonus>,
+    // Set when the holder is kept out of rewards; stamped whenever the record is stored
+    pub excluded: Option<ExclusionReason>,
+    // Account rewards are paid to; filled in when holders are read, never stored
+    pub reward_recipient: Option<Account>,
+}
+
+impl HolderInfo {
+    // Build a holder record from per-collection counts, deriving the legacy fields
+    pub fn from_holdings(mut holdings: Vec<CollectionHolding>, last_updated: u64) -> Self {
+        // Stale entries stay even at zero so callers can see the collection failed
+        holdings.retain(|holding| holding.count > 0 || holding.stale.is_some());
+        holdings.sort_by_key(|holding| holding.collection);
+
+        let daku_canister = collections::daku_canister();
+        let gg_canister = collections::gg_canister();
+        let count_of = |canister: &Principal| {
+            holdings.iter().filter(|h| h.collection == *canister).map(|h| h.count).sum::<u64>()
+        };
+
+        let milli: Vec<(Principal, u64)> = holdings.iter()
+            .map(|holding| (holding.collection, holding.count.saturating_mul(1000)))
+            .collect();
+
+        HolderInfo {
+            daku_count: count_of(&daku_canister),
+            gg_count: count_of(&gg_canister),
+            total_count: holdings.iter().map(|h| h.count).sum(),
+            last_updated,
+            collections: Some(holdings),
+            set_bonus: rewards::set_bonus(&rewards::policy(), &milli),
+            excluded: None,
+            reward_recipient: None,
+        }
+    }
+
+    // Re-evaluate the set bonus after the reward policy changed; true if it differs
+    pub fn refresh_set_bonus(&mut self, policy: &rewards::RewardPolicy) -> bool {
+        let milli: Vec<(Principal, u64)> = self.holdings().iter()
+            .map(|holding| (holding.collection, holding.count.saturating_mul(1000)))
+            .collect();
+        let bonus = rewards::set_bonus(policy, &milli);
+        let changed = bonus != self.set_bonus;
+        self.set_bonus = bonus;
+        changed
+    }
+
+    // Per-collection counts, falling back to the legacy fields for pre-v2 records
+    pub fn holdings(&self) -> Vec<CollectionHolding> {
+        match &self.collections {
+            Some(holdings) => holdings.clone(),
+            None => vec![
+                CollectionHolding::new(collections::daku_canister(), self.daku_count),
+                CollectionHolding::new(collections::gg_canister(), self.gg_count),
+            ],
+        }
+    }
+
+    // True when any count was carried over from an earlier read
+    pub fn is_degraded(&self) -> bool {
+        self.collections.iter().flatten().any(|holding| holding.stale.is_some())
+    }
+
+    // Our previous count for `collection`, marked stale after a failed read so it isn't lost
+    pub fn stale_holding(&self, collection: &Principal, error: &str, failed_at: u64) -> Option<CollectionHolding> {
+        let previous = self.holdings().into_iter().find(|holding| holding.collection == *collection)?;
+        let confirmed_at = match &previous.stale {
+            Some(stale) => stale.confirmed_at,
+            None => Some(self.last_updated),
+        };
+        Some(CollectionHolding {
+            stale: Some(StaleHolding::new(error, failed_at, confirmed_at)),
+            ..previous
+        })
+    }
 }
 
 #[derive(Debug, Clone, CandidType, Deserialize)]
@@ -19,123 +106,216 @@ pub struct CSVHolderEntry {
     pub number_of_tokens: u64,
 }
 
-// Function to load Daku Motoko holders
-pub fn load_daku_holders(csv_data: &str) -> HashMap<Principal, u64> {
-    let mut holders = HashMap::new();
-    
-    // Skip header
-    let lines = csv_data.split('\n').skip(1);
-    
-    for line in lines {
-        if line.trim().is_empty() {
-            continue;
-        }
-        
-        let parts: Vec<&str> = line.split(',').collect();
-        if parts.len() < 4 {
-            ic_cdk::print(format!("Invalid CSV line: {}", line));
-            continue;
-        }
-        
-        // Extract principal
-        let principal_str = parts[1].trim();
-        if principal_str.is_empty() {
-            continue; // Skip entries with no principal
-        }
-        
-        // Parse principal
-        match Principal::from_text(principal_str) {
-            Ok(principal) => {
-                // Extract number of tokens
-                if let Ok(num_tokens) = parts[3].trim().parse::<u64>() {
-                    *holders.entry(principal).or_insert(0) += num_tokens;
-                }
-            },
-            Err(e) => {
-                ic_cdk::print(format!("Invalid principal {}: {}", principal_str, e));
-            }
-        }
+// Cap on rows listed individually in a report; the totals still count every row
+pub const MAX_REPORTED_ROWS: usize = 200;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RejectedRow {
+    pub line: u64,
+    pub reason: String,
+}
+
+// numberOfTokens disagrees with the length of tokenIds; numberOfTokens is what gets imported
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CountMismatch {
+    pub line: u64,
+    pub principal: Principal,
+    pub number_of_tokens: u64,
+    pub token_ids: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Default, Debug)]
+pub struct CsvFileReport {
+    pub accepted_rows: u64,
+    // Rows with an accountIdentifier but no principal; only used to bind account ids
+    pub rows_without_principal: u64,
+    pub rejected_rows: u64,
+    pub rejected: Vec<RejectedRow>,
+    pub mismatched_rows: u64,
+    pub mismatches: Vec<CountMismatch>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Default, Debug)]
+pub struct CsvImportReport {
+    pub daku: CsvFileReport,
+    pub gg: CsvFileReport,
+    pub holders: u64,
+}
+
+// A holder export after parsing: per-principal totals, the token ids listed for each
+// and the account ids seen for them
+#[derive(Clone, Default)]
+pub struct ParsedHolderCsv {
+    pub counts: HashMap<Principal, u64>,
+    // None when the export has no tokenIds column, so stored ownership is left alone
+    pub tokens: Option<HashMap<Principal, Vec<TokenIndex>>>,
+    pub bindings: Vec<(String, Principal)>,
+    pub report: CsvFileReport,
+}
+
+// Column positions resolved from the header row
+#[derive(Clone)]
+struct HolderColumns {
+    account_identifier: Option<usize>,
+    principal: usize,
+    token_ids: Option<usize>,
+    number_of_tokens: usize,
+}
+
+impl HolderColumns {
+    fn from_header(header: &CsvRecord) -> Result<Self, WalletError> {
+        let find = |name: &str| header.fields.iter()
+            .position(|field| field.trim().eq_ignore_ascii_case(name));
+        let require = |name: &str| find(name).ok_or_else(|| WalletError::CsvParse {
+            line: header.line,
+            reason: format!("header has no '{}' column", name),
+        });
+
+        Ok(HolderColumns {
+            account_identifier: find("accountIdentifier"),
+            principal: require("principal")?,
+            token_ids: find("tokenIds"),
+            number_of_tokens: require("numberOfTokens")?,
+        })
     }
-    
-    holders
 }
 
-// Function to load GG Album Release holders
-pub fn load_gg_holders(csv_data: &str) -> HashMap<Principal, u64> {
-    let mut holders = HashMap::new();
-    
-    // Skip header
-    let lines = csv_data.split('\n').skip(1);
-    
-    for line in lines {
-        if line.trim().is_empty() {
-            continue;
-        }
-        
-        let parts: Vec<&str> = line.split(',').collect();
-        if parts.len() < 4 {
-            ic_cdk::print(format!("Invalid CSV line: {}", line));
-            continue;
+// Token ids are exported separated by ';' (or ',' inside a quoted field)
+fn split_token_ids(token_ids: &str) -> impl Iterator<Item = &str> {
+    token_ids.split([';', ','])
+        .map(|id| id.trim())
+        .filter(|id| !id.is_empty())
+}
+
+// Builds a ParsedHolderCsv from records as they are read, so large exports can be
+// processed chunk by chunk. The first record is the header.
+#[derive(Clone, Default)]
+pub struct HolderCsvAccumulator {
+    header_len: usize,
+    columns: Option<HolderColumns>,
+    parsed: ParsedHolderCsv,
+}
+
+impl HolderCsvAccumulator {
+    pub fn rows_read(&self) -> u64 {
+        let report = &self.parsed.report;
+        report.accepted_rows + report.rows_without_principal + report.rejected_rows
+    }
+
+    // Malformed rows are rejected individually and listed in the report; only a header
+    // without the required columns fails the whole import.
+    pub fn push(&mut self, row: &CsvRecord) -> Result<(), WalletError> {
+        let Some(columns) = &self.columns else {
+            self.columns = Some(HolderColumns::from_header(row)?);
+            self.header_len = row.fields.len();
+            if self.columns.as_ref().is_some_and(|columns| columns.token_ids.is_some()) {
+                self.parsed.tokens = Some(HashMap::new());
+            }
+            return Ok(());
+        };
+        let parsed = &mut self.parsed;
+        let report = &mut parsed.report;
+        let mut reject = |reason: String| {
+            report.rejected_rows += 1;
+            if report.rejected.len() < MAX_REPORTED_ROWS {
+                report.rejected.push(RejectedRow { line: row.line, reason });
+            }
+        };
+
+        if row.fields.len() != self.header_len {
+            reject(format!("expected {} columns, found {}", self.header_len, row.fields.len()));
+            return Ok(());
         }
-        
-        // Extract principal
-        let principal_str = parts[1].trim();
+
+        let account_identifier = columns.account_identifier.map(|i| row.field(i)).unwrap_or("");
+        let principal_str = row.field(columns.principal);
         if principal_str.is_empty() {
-            continue; // Skip entries with no principal
+            report.rows_without_principal += 1;
+            return Ok(());
         }
-        
-        // Parse principal
-        match Principal::from_text(principal_str) {
-            Ok(principal) => {
-                // Extract number of tokens
-                if let Ok(num_tokens) = parts[3].trim().parse::<u64>() {
-                    *holders.entry(principal).or_insert(0) += num_tokens;
-                }
-            },
+        let principal = match Principal::from_text(principal_str) {
+            Ok(principal) => principal,
+            Err(e) => {
+                reject(format!("invalid principal '{}': {}", principal_str, e));
+                return Ok(());
+            }
+        };
+        let number_of_tokens = match row.field(columns.number_of_tokens).parse::<u64>() {
+            Ok(count) => count,
             Err(e) => {
-                ic_cdk::print(format!("Invalid principal {}: {}", principal_str, e));
+                reject(format!("invalid numberOfTokens '{}': {}", row.field(columns.number_of_tokens), e));
+                return Ok(());
+            }
+        };
+
+        if let Some(index) = columns.token_ids {
+            let ids: Vec<&str> = split_token_ids(row.field(index)).collect();
+            // Ids that aren't token indices still count towards the mismatch check
+            parsed.tokens.get_or_insert_with(HashMap::new).entry(principal).or_default()
+                .extend(ids.iter().filter_map(|id| id.parse::<TokenIndex>().ok()));
+            let token_ids = ids.len() as u64;
+            if token_ids != number_of_tokens {
+                report.mismatched_rows += 1;
+                if report.mismatches.len() < MAX_REPORTED_ROWS {
+                    report.mismatches.push(CountMismatch { line: row.line, principal, number_of_tokens, token_ids });
+                }
             }
         }
+
+        report.accepted_rows += 1;
+        *parsed.counts.entry(principal).or_insert(0) += number_of_tokens;
+        if !account_identifier.is_empty() {
+            parsed.bindings.push((account_identifier.to_string(), principal));
+        }
+        Ok(())
+    }
+
+    // Input without even a header row is a truncated upload or the wrong argument, so it
+    // fails. A header with no rows is the explicit way to clear a collection.
+    pub fn finish(self) -> Result<ParsedHolderCsv, WalletError> {
+        if self.columns.is_none() {
+            return Err(WalletError::CsvParse { line: 0, reason: "no header row".to_string() });
+        }
+        Ok(self.parsed)
     }
-    
-    holders
 }
 
-// Function to load both CSV files and merge the data
-pub fn load_all_holders(daku_csv: &str, gg_csv: &str) -> HashMap<Principal, HolderInfo> {
-    let daku_holders = load_daku_holders(daku_csv);
-    let gg_holders = load_gg_holders(gg_csv);
-    
-    let mut combined_holders = HashMap::new();
+// Parse a complete holder export (accountIdentifier,principal,tokenIds,numberOfTokens, in any column order)
+pub fn parse_holder_csv(csv_data: &str) -> Result<ParsedHolderCsv, WalletError> {
+    let mut rows = HolderCsvAccumulator::default();
+    for record in csv_reader::parse_records(csv_data)? {
+        rows.push(&record)?;
+    }
+    rows.finish()
+}
+
+// Merge the parsed Daku and GG exports into one holder snapshot
+pub fn merge_holder_csvs(daku: &ParsedHolderCsv, gg: &ParsedHolderCsv) -> (HashMap<Principal, HolderInfo>, CsvImportReport) {
+    let mut holdings: HashMap<Principal, Vec<CollectionHolding>> = HashMap::new();
     let current_time = time();
     
     // Process Daku holders
-    for (principal, count) in daku_holders {
-        let info = combined_holders.entry(principal).or_insert(HolderInfo {
-            daku_count: 0,
-            gg_count: 0,
-            total_count: 0,
-            last_updated: current_time,
-        });
-        
-        info.daku_count = count;
-        info.total_count += count;
+    let daku_canister = collections::daku_canister();
+    for (principal, count) in daku.counts.iter().map(|(p, c)| (*p, *c)) {
+        holdings.entry(principal).or_default().push(CollectionHolding::new(daku_canister, count));
     }
     
     // Process GG holders
-    for (principal, count) in gg_holders {
-        let info = combined_holders.entry(principal).or_insert(HolderInfo {
-            daku_count: 0,
-            gg_count: 0,
-            total_count: 0,
-            last_updated: current_time,
-        });
-        
-        info.gg_count = count;
-        info.total_count += count;
+    let gg_canister = collections::gg_canister();
+    for (principal, count) in gg.counts.iter().map(|(p, c)| (*p, *c)) {
+        holdings.entry(principal).or_default().push(CollectionHolding::new(gg_canister, count));
     }
     
-    combined_holders
+    let holders: HashMap<Principal, HolderInfo> = holdings.into_iter()
+        .map(|(principal, holdings)| (principal, HolderInfo::from_holdings(holdings, current_time)))
+        .collect();
+    let report = CsvImportReport {
+        daku: daku.report.clone(),
+        gg: gg.report.clone(),
+        holders: holders.len() as u64,
+    };
+    
+    (holders, report)
 }
 
 // Test function to generate CSV sample for testing
@@ -0,0 +1,166 @@
+use crate::errors::WalletError;
+
+// A parsed CSV record and the 1-based line it starts on
+#[derive(Clone, Debug)]
+pub struct CsvRecord {
+    pub line: u64,
+    pub fields: Vec<String>,
+}
+
+impl CsvRecord {
+    pub fn field(&self, index: usize) -> &str {
+        self.fields.get(index).map(|f| f.trim()).unwrap_or("")
+    }
+}
+
+// Incremental RFC 4180 reader: quoted fields may contain commas, doubled quotes and
+// line breaks; CRLF and LF line endings are both accepted (a CR anywhere else is data)
+// and a leading UTF-8 BOM is ignored. Blank lines are skipped. Input can be fed in
+// arbitrary pieces.
+#[derive(Clone, Debug)]
+pub struct CsvReader {
+    line: u64,
+    record_line: u64,
+    fields: Vec<String>,
+    field: String,
+    at_start: bool,
+    in_quotes: bool,
+    // Saw '"' inside a quoted field; the next char decides between "" and a closing quote
+    quote_pending: bool,
+    // Set after a closing quote; only a delimiter or line end may follow
+    after_quote: bool,
+    // Saw '\r' outside quotes; dropped if '\n' follows, even in the next piece
+    cr_pending: bool,
+}
+
+impl Default for CsvReader {
+    fn default() -> Self {
+        CsvReader {
+            line: 1,
+            record_line: 1,
+            fields: Vec::new(),
+            field: String::new(),
+            at_start: true,
+            in_quotes: false,
+            quote_pending: false,
+            after_quote: false,
+            cr_pending: false,
+        }
+    }
+}
+
+impl CsvReader {
+    // Consume the next piece of input and return the records it completed
+    pub fn feed(&mut self, input: &str) -> Result<Vec<CsvRecord>, WalletError> {
+        let mut records = Vec::new();
+        for c in input.chars() {
+            if self.at_start {
+                self.at_start = false;
+                if c == '\u{feff}' {
+                    continue;
+                }
+            }
+            self.push_char(c, &mut records)?;
+        }
+        Ok(records)
+    }
+
+    // Flush the final record once all input has been fed
+    pub fn finish(mut self) -> Result<Vec<CsvRecord>, WalletError> {
+        if self.in_quotes && !self.quote_pending {
+            return Err(WalletError::CsvParse {
+                line: self.record_line,
+                reason: "unterminated quoted field".to_string(),
+            });
+        }
+        self.flush_cr(None);
+        let mut records = Vec::new();
+        self.end_record(&mut records);
+        Ok(records)
+    }
+
+    // Keep a pending CR as field data unless `next` makes it part of a CRLF
+    fn flush_cr(&mut self, next: Option<char>) {
+        if std::mem::take(&mut self.cr_pending) && next != Some('\n') && !self.after_quote {
+            self.field.push('\r');
+        }
+    }
+
+    fn push_char(&mut self, c: char, records: &mut Vec<CsvRecord>) -> Result<(), WalletError> {
+        self.flush_cr(Some(c));
+        if self.quote_pending {
+            self.quote_pending = false;
+            if c == '"' {
+                self.field.push('"');
+                return Ok(());
+            }
+            self.in_quotes = false;
+            self.after_quote = true;
+        }
+
+        if self.in_quotes {
+            match c {
+                '"' => self.quote_pending = true,
+                '\n' => {
+                    self.line += 1;
+                    self.field.push(c);
+                },
+                _ => self.field.push(c),
+            }
+            return Ok(());
+        }
+
+        match c {
+            ',' => {
+                self.fields.push(std::mem::take(&mut self.field));
+                self.after_quote = false;
+            },
+            // Line endings are recognised by '\n'; a CR right before it is dropped
+            '\r' => self.cr_pending = true,
+            '\n' => {
+                self.end_record(records);
+                self.line += 1;
+                self.record_line = self.line;
+            },
+            '"' if self.field.trim().is_empty() && !self.after_quote => {
+                // Whitespace before an opening quote is dropped
+                self.field.clear();
+                self.in_quotes = true;
+            },
+            _ if self.after_quote => {
+                if !c.is_whitespace() {
+                    return Err(WalletError::CsvParse {
+                        line: self.line,
+                        reason: format!("unexpected '{}' after closing quote", c),
+                    });
+                }
+            },
+            '"' => {
+                return Err(WalletError::CsvParse {
+                    line: self.line,
+                    reason: "quote inside an unquoted field".to_string(),
+                });
+            },
+            _ => self.field.push(c),
+        }
+        Ok(())
+    }
+
+    fn end_record(&mut self, records: &mut Vec<CsvRecord>) {
+        self.fields.push(std::mem::take(&mut self.field));
+        self.after_quote = false;
+        let fields = std::mem::take(&mut self.fields);
+        let blank = fields.len() == 1 && fields[0].trim().is_empty();
+        if !blank {
+            records.push(CsvRecord { line: self.record_line, fields });
+        }
+    }
+}
+
+// Parse a complete CSV document
+pub fn parse_records(input: &str) -> Result<Vec<CsvRecord>, WalletError> {
+    let mut reader = CsvReader::default();
+    let mut records = reader.feed(input)?;
+    records.extend(reader.finish()?);
+    Ok(records)
+}
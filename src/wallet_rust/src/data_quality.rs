@@ -0,0 +1,224 @@
+use candid::{CandidType, Principal};
+use ic_stable_structures::StableCell;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::account_index;
+use crate::nft_registry_interface::TokenIndex;
+use crate::state::{self, Memory, DATA_QUALITY_MEMORY_ID};
+
+// Findings listed individually per kind; totals still count all of them
+const MAX_LISTED_FINDINGS: usize = 50;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QualitySource {
+    CsvImport,
+    Registry,
+}
+
+// One token index attributed to more than one holder
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TokenConflict {
+    pub token_index: TokenIndex,
+    pub owners: Vec<Principal>,
+}
+
+// A holder credited with more tokens than the collection has
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct SupplyExcess {
+    pub holder: Principal,
+    pub count: u64,
+    pub supply: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct AccountConflict {
+    pub account_id: String,
+    pub principals: Vec<Principal>,
+}
+
+// Result of the last check of one collection
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CollectionQuality {
+    pub collection: Principal,
+    pub source: QualitySource,
+    pub checked_at: u64,
+    // Token count of the collection as last read from its registry, or as listed in the
+    // import when the registry was never read; None if neither is known
+    pub supply: Option<u64>,
+    pub token_conflict_count: u64,
+    pub token_conflicts: Vec<TokenConflict>,
+    pub supply_excess_count: u64,
+    pub supply_excesses: Vec<SupplyExcess>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Default, Debug)]
+pub struct DataQualityReport {
+    pub collections: Vec<CollectionQuality>,
+    // accountIdentifiers whose latest import bindings disagree, across all CSV imports.
+    // The list holds the first MAX_LISTED_FINDINGS; the count covers all of them.
+    pub account_conflict_count: u64,
+    pub account_conflicts: Vec<AccountConflict>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Default, Debug)]
+struct DataQualityState {
+    report: DataQualityReport,
+    // Supply seen in each collection's registry, used to check CSV imports
+    registry_supply: BTreeMap<Principal, u64>,
+    // Every accountIdentifier currently in conflict; report.account_conflicts lists
+    // only the first MAX_LISTED_FINDINGS of them
+    account_conflict_ids: Option<BTreeSet<String>>,
+}
+
+state::impl_candid_storable!(DataQualityState, 256 * 1024);
+
+thread_local! {
+    static QUALITY: RefCell<StableCell<DataQualityState, Memory>> = RefCell::new(
+        StableCell::init(state::memory(DATA_QUALITY_MEMORY_ID), DataQualityState::default())
+            .expect("Failed to init data quality cell")
+    );
+}
+
+pub fn report() -> DataQualityReport {
+    QUALITY.with(|quality| quality.borrow().get().report.clone())
+}
+
+fn update_state<F: FnOnce(&mut DataQualityState)>(f: F) {
+    QUALITY.with(|quality| {
+        let mut cell = quality.borrow_mut();
+        let mut value = cell.get().clone();
+        f(&mut value);
+        cell.set(value).expect("Failed to write data quality report");
+    });
+}
+
+pub fn registry_supply(collection: &Principal) -> Option<u64> {
+    QUALITY.with(|quality| quality.borrow().get().registry_supply.get(collection).copied())
+}
+
+// Check one collection's token attributions and per-holder counts. `tokens` may list the
+// same index more than once; an index with more than one distinct owner is a conflict.
+pub fn check_collection(
+    collection: Principal,
+    source: QualitySource,
+    tokens: &[(TokenIndex, Principal)],
+    counts: &HashMap<Principal, u64>,
+    supply: Option<u64>,
+    checked_at: u64,
+) -> CollectionQuality {
+    let mut owners: BTreeMap<TokenIndex, BTreeSet<Principal>> = BTreeMap::new();
+    for (index, owner) in tokens {
+        owners.entry(*index).or_default().insert(*owner);
+    }
+    let conflicts: Vec<TokenConflict> = owners.into_iter()
+        .filter(|(_, owners)| owners.len() > 1)
+        .map(|(token_index, owners)| TokenConflict { token_index, owners: owners.into_iter().collect() })
+        .collect();
+
+    let mut excesses: Vec<SupplyExcess> = match supply {
+        Some(supply) => counts.iter()
+            .filter(|(_, count)| **count > supply)
+            .map(|(holder, count)| SupplyExcess { holder: *holder, count: *count, supply })
+            .collect(),
+        None => Vec::new(),
+    };
+    excesses.sort_by(|a, b| b.count.cmp(&a.count).then(a.holder.cmp(&b.holder)));
+
+    CollectionQuality {
+        collection,
+        source,
+        checked_at,
+        supply,
+        token_conflict_count: conflicts.len() as u64,
+        token_conflicts: conflicts.into_iter().take(MAX_LISTED_FINDINGS).collect(),
+        supply_excess_count: excesses.len() as u64,
+        supply_excesses: excesses.into_iter().take(MAX_LISTED_FINDINGS).collect(),
+    }
+}
+
+// Keep `quality` as the latest result for its collection. A registry check also
+// records the supply it saw.
+pub fn record(quality: &CollectionQuality) {
+    if quality.token_conflict_count > 0 || quality.supply_excess_count > 0 {
+        ic_cdk::print(format!("Data quality: collection {} has {} conflicting tokens and {} holders above supply",
+            quality.collection, quality.token_conflict_count, quality.supply_excess_count));
+    }
+    update_state(|state| {
+        if let (QualitySource::Registry, Some(supply)) = (quality.source, quality.supply) {
+            state.registry_supply.insert(quality.collection, supply);
+        }
+        let collections = &mut state.report.collections;
+        collections.retain(|existing| existing.collection != quality.collection);
+        collections.push(quality.clone());
+        collections.sort_by_key(|existing| existing.collection);
+    });
+}
+
+pub fn forget_collection(collection: &Principal) {
+    update_state(|state| {
+        state.registry_supply.remove(collection);
+        state.report.collections.retain(|existing| existing.collection != *collection);
+    });
+}
+
+// Check the accountIdentifier bindings of an import before they are indexed. Conflicts
+// are added to the report; accounts the import binds to a single principal again are
+// cleared from it, since the index now agrees with the latest data.
+pub fn check_account_bindings(bindings: &[(String, Principal)]) {
+    let conflicts = account_conflicts(bindings);
+    if !conflicts.is_empty() {
+        ic_cdk::print(format!("Data quality: {} accountIdentifiers map to more than one principal", conflicts.len()));
+    }
+    let conflicted: BTreeSet<String> = conflicts.iter().map(|conflict| conflict.account_id.clone()).collect();
+    let resolved: BTreeSet<String> = bindings.iter()
+        .map(|(account_id, _)| account_id.to_ascii_lowercase())
+        .filter(|account_id| !conflicted.contains(account_id))
+        .collect();
+
+    update_state(|state| {
+        let report = &mut state.report;
+        // Reports written before the id set existed only know their listed findings
+        let ids = state.account_conflict_ids.get_or_insert_with(|| {
+            report.account_conflicts.iter().map(|conflict| conflict.account_id.clone()).collect()
+        });
+        ids.retain(|account_id| !resolved.contains(account_id));
+        report.account_conflicts.retain(|existing| !resolved.contains(&existing.account_id));
+
+        for conflict in conflicts {
+            ids.insert(conflict.account_id.clone());
+            match report.account_conflicts.iter_mut().find(|existing| existing.account_id == conflict.account_id) {
+                Some(existing) => {
+                    existing.principals.extend(conflict.principals);
+                    existing.principals.sort();
+                    existing.principals.dedup();
+                },
+                None => {
+                    if report.account_conflicts.len() < MAX_LISTED_FINDINGS {
+                        report.account_conflicts.push(conflict);
+                    }
+                },
+            }
+        }
+        report.account_conflict_count = ids.len() as u64;
+    });
+}
+
+// accountIdentifiers that `bindings` pair with more than one principal, either within
+// `bindings` or against the principal they are already indexed to
+fn account_conflicts(bindings: &[(String, Principal)]) -> Vec<AccountConflict> {
+    let mut principals: BTreeMap<String, BTreeSet<Principal>> = BTreeMap::new();
+    for (account_id, principal) in bindings {
+        principals.entry(account_id.to_ascii_lowercase()).or_default().insert(*principal);
+    }
+    for (account_id, seen) in principals.iter_mut() {
+        if let Some(indexed) = account_index::lookup(account_id) {
+            seen.insert(indexed.principal);
+        }
+    }
+    principals.into_iter()
+        .filter(|(_, principals)| principals.len() > 1)
+        .map(|(account_id, principals)| AccountConflict { account_id, principals: principals.into_iter().collect() })
+        .collect()
+}
@@ -0,0 +1,62 @@
+use candid::{CandidType, Principal};
+use ic_cdk::api::call::RejectionCode;
+use serde::Deserialize;
+use std::fmt;
+
+// Errors returned to callers of the public endpoints
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum WalletError {
+    // Caller is neither a registered admin nor a controller of this canister
+    Unauthorized,
+    // Text that should have been a principal or canister id didn't parse
+    InvalidPrincipal(String),
+    // An inter-canister call was rejected
+    CanisterUnreachable { code: RejectionCode, msg: String },
+    // The call succeeded but the reply wasn't in any format we understand
+    DecodeFailed(String),
+    // Uploaded CSV is malformed; `line` is 1-based and counts the header, 0 if there is none
+    CsvParse { line: u64, reason: String },
+    // No collection is registered under this canister id
+    CollectionNotFound(Principal),
+    // Argument failed validation; the message says which one and why
+    InvalidArgument(String),
+    // Another holder refresh is still running; see get_refresh_job
+    RefreshInProgress,
+    // HOLDER_INFO changed since the caller started paging; restart from the first page
+    SnapshotChanged { requested: u64, current: u64 },
+    // The caller refreshed its own holdings too recently
+    CooldownActive { retry_after_secs: u64 },
+    // Self-service refreshes used up the canister-wide budget for this window
+    RefreshBudgetExhausted { retry_after_secs: u64 },
+}
+
+impl From<(RejectionCode, String)> for WalletError {
+    fn from((code, msg): (RejectionCode, String)) -> Self {
+        WalletError::CanisterUnreachable { code, msg }
+    }
+}
+
+// Used where errors end up in logs or stored reports
+impl fmt::Display for WalletError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WalletError::Unauthorized => write!(f, "caller is not an admin"),
+            WalletError::InvalidPrincipal(text) => write!(f, "invalid principal '{}'", text),
+            WalletError::CanisterUnreachable { code, msg } => write!(f, "call rejected: {:?} - {}", code, msg),
+            WalletError::DecodeFailed(msg) => write!(f, "could not decode reply: {}", msg),
+            WalletError::CsvParse { line, reason } => write!(f, "CSV line {}: {}", line, reason),
+            WalletError::CollectionNotFound(canister_id) => write!(f, "collection {} is not registered", canister_id),
+            WalletError::InvalidArgument(msg) => write!(f, "invalid argument: {}", msg),
+            WalletError::RefreshInProgress => write!(f, "a holder refresh is already running"),
+            WalletError::SnapshotChanged { requested, current } => {
+                write!(f, "snapshot {} is no longer current (now {})", requested, current)
+            },
+            WalletError::CooldownActive { retry_after_secs } => {
+                write!(f, "refreshed too recently, retry in {}s", retry_after_secs)
+            },
+            WalletError::RefreshBudgetExhausted { retry_after_secs } => {
+                write!(f, "refresh budget used up, retry in {}s", retry_after_secs)
+            },
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use candid::{CandidType, Principal};
+use ic_cdk::api::time;
+use ic_stable_structures::{StableBTreeMap, StableCell};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+
+use crate::errors::WalletError;
+use crate::state::{self, Memory, StablePrincipal, EXCLUSIONS_MEMORY_ID, EXCLUSION_CONFIG_MEMORY_ID};
+
+const MAX_NOTE_LEN: usize = 128;
+
+// Why a principal is kept out of rewards
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum ExclusionReason {
+    // On the admin-managed list, with the note it was added with
+    Listed(String),
+    // An opaque id, i.e. a canister, while auto-exclusion of canisters is on
+    CanisterPrincipal,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Exclusion {
+    pub note: String,
+    pub added_by: Principal,
+    pub added_at: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Default, Debug)]
+pub struct ExclusionConfig {
+    pub auto_exclude_canisters: bool,
+}
+
+state::impl_candid_storable!(Exclusion, 256);
+state::impl_candid_storable!(ExclusionConfig, 64);
+
+thread_local! {
+    // Treasury, marketplace escrow and other wallets that hold NFTs but earn nothing
+    static EXCLUSIONS: RefCell<StableBTreeMap<StablePrincipal, Exclusion, Memory>> =
+        RefCell::new(StableBTreeMap::init(state::memory(EXCLUSIONS_MEMORY_ID)));
+
+    static CONFIG: RefCell<StableCell<ExclusionConfig, Memory>> = RefCell::new(
+        StableCell::init(state::memory(EXCLUSION_CONFIG_MEMORY_ID), ExclusionConfig::default())
+            .expect("Failed to init exclusion config cell")
+    );
+}
+
+// Canister ids are opaque ids, whose last byte is the 0x01 class tag
+pub fn is_canister_principal(principal: &Principal) -> bool {
+    principal.as_slice().last() == Some(&0x01)
+}
+
+pub fn reason_for(principal: &Principal) -> Option<ExclusionReason> {
+    if let Some(exclusion) = EXCLUSIONS.with(|list| list.borrow().get(&StablePrincipal(*principal))) {
+        return Some(ExclusionReason::Listed(exclusion.note));
+    }
+    if config().auto_exclude_canisters && is_canister_principal(principal) {
+        return Some(ExclusionReason::CanisterPrincipal);
+    }
+    None
+}
+
+pub fn is_excluded(principal: &Principal) -> bool {
+    reason_for(principal).is_some()
+}
+
+pub fn add(principal: Principal, note: String, added_by: Principal) -> Result<(), WalletError> {
+    if note.len() > MAX_NOTE_LEN {
+        return Err(WalletError::InvalidArgument(format!("exclusion note is longer than {} bytes", MAX_NOTE_LEN)));
+    }
+    EXCLUSIONS.with(|list| {
+        list.borrow_mut().insert(StablePrincipal(principal), Exclusion { note, added_by, added_at: time() });
+    });
+    Ok(())
+}
+
+pub fn remove(principal: &Principal) -> bool {
+    EXCLUSIONS.with(|list| list.borrow_mut().remove(&StablePrincipal(*principal)).is_some())
+}
+
+pub fn list() -> Vec<(Principal, Exclusion)> {
+    EXCLUSIONS.with(|list| list.borrow().iter().map(|(k, v)| (k.0, v)).collect())
+}
+
+pub fn config() -> ExclusionConfig {
+    CONFIG.with(|config| config.borrow().get().clone())
+}
+
+pub fn set_auto_exclude_canisters(enabled: bool) -> ExclusionConfig {
+    let config = ExclusionConfig { auto_exclude_canisters: enabled };
+    CONFIG.with(|cell| cell.borrow_mut().set(config.clone()).expect("Failed to write exclusion config"));
+    config
+}
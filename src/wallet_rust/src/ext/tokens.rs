@@ -55,13 +55,11 @@ pub enum CommonError {
 pub fn principal_to_account_id(principal: &Principal, subaccount: Option<Vec<u8>>) -> Vec<u8> {
     let mut hasher = Sha224::new();
     
-    // Start with \x0Aaccount-id
+    // Start with \x0Aaccount-id (the leading byte is the length of the domain separator)
     hasher.update(b"\x0Aaccount-id");
     
-    // Add principal
-    let principal_bytes = principal.as_slice();
-    hasher.update([principal_bytes.len() as u8]);
-    hasher.update(principal_bytes);
+    // Add principal bytes as-is - the ledger does not length-prefix them
+    hasher.update(principal.as_slice());
     
     // Add subaccount (0 for default) - must be 32 bytes
     let subaccount_bytes = subaccount.unwrap_or(vec![0; 32]);
@@ -71,6 +69,21 @@ pub fn principal_to_account_id(principal: &Principal, subaccount: Option<Vec<u8>
     hasher.finalize().to_vec()
 }
 
+// Textual AccountIdentifier as returned by EXT registries: hex(crc32(hash) ++ hash)
+pub fn principal_to_account_id_hex(principal: &Principal, subaccount: Option<Vec<u8>>) -> String {
+    let hash = principal_to_account_id(principal, subaccount);
+    let mut bytes = crc32fast::hash(&hash).to_be_bytes().to_vec();
+    bytes.extend_from_slice(&hash);
+    hex::encode(bytes)
+}
+
+// Subaccount number `index` encoded big-endian into the last bytes of a 32-byte subaccount
+pub fn subaccount_from_index(index: u32) -> Vec<u8> {
+    let mut subaccount = vec![0; 32];
+    subaccount[28..].copy_from_slice(&index.to_be_bytes());
+    subaccount
+}
+
 // Convert Principal to EXT User format - try both Address and Principal formats
 pub fn principal_to_user_variants(principal: &Principal) -> Vec<User> {
     vec![
@@ -78,7 +91,7 @@ pub fn principal_to_user_variants(principal: &Principal) -> Vec<User> {
         User::Principal(*principal),
         
         // Try with Address format (textual AccountIdentifier)
-        User::Address(hex::encode(principal_to_account_id(principal, None))),
+        User::Address(principal_to_account_id_hex(principal, None)),
     ]
 }
 
@@ -98,14 +111,14 @@ pub fn create_tokens_query_encodings(principal: &Principal) -> HashMap<String, V
     
     // Try with AccountIdentifier hash format (common in EXT)
     let account_id_hash = principal_to_account_id(principal, None);
-    let account_id = AccountIdentifier { hash: account_id_hash.clone() };
+    let account_id = AccountIdentifier { hash: account_id_hash };
     
     if let Ok(encoded) = candid::encode_one(account_id) {
         encodings.insert("account_id".to_string(), encoded);
     }
     
     // Try with hex-encoded account ID (some implementations expect this)
-    if let Ok(encoded) = candid::encode_one(hex::encode(account_id_hash)) {
+    if let Ok(encoded) = candid::encode_one(principal_to_account_id_hex(principal, None)) {
         encodings.insert("account_id_hex".to_string(), encoded);
     }
     
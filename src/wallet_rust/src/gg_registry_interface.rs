@@ -2,6 +2,8 @@ use candid::{CandidType, Nat, Principal};
 use ic_cdk::api::call::RejectionCode;
 use std::collections::HashMap;
 
+use crate::account_index::{account_ids_of, resolve_registry};
+
 // Define GG Album registry types from the provided Candid definitions
 pub type TokenIndex = u32;
 pub type AccountIdentifier1 = String;
@@ -90,7 +92,7 @@ pub async fn get_gg_registry_tokens(
 // Get registry entries as a map of TokenIndex -> Principal
 pub async fn get_gg_registry_map(
     canister_id: Principal,
-) -> Result<HashMap<TokenIndex, Principal>, (RejectionCode, String)> {
+) -> Result<(HashMap<TokenIndex, Principal>, u64), (RejectionCode, String)> {
     // Call getRegistry with the correct interface
     match ic_cdk::api::call::call::<(), (Vec<(TokenIndex, AccountIdentifier1)>,)>(
         canister_id,
@@ -98,15 +100,10 @@ pub async fn get_gg_registry_map(
         ()
     ).await {
         Ok((records,)) => {
-            // Convert to HashMap, trying to interpret AccountIdentifier1 as Principal
-            let mut map = HashMap::new();
-            for (index, owner_id) in records {
-                // Try to convert the owner_id to a principal
-                if let Ok(principal) = Principal::from_text(&owner_id) {
-                    map.insert(index, principal);
-                }
-            }
-            Ok(map)
+            // Resolve AccountIdentifier1 owners through the account-id index;
+            // the second value counts owners we couldn't map to a principal
+            let (entries, unresolved) = resolve_registry(records);
+            Ok((entries.into_iter().collect(), unresolved))
         },
         Err(err) => Err(err)
     }
@@ -120,12 +117,13 @@ pub async fn get_gg_tokens_for_owner(
     // Get all registry entries
     match get_gg_registry_records(canister_id).await {
         Ok(records) => {
-            // Check if the owner matches either directly or through account identifier
+            // Registry owners are AccountIdentifiers, so match against every account we know for the owner
             let owner_text = owner.to_text();
+            let account_ids = account_ids_of(&owner);
             let tokens: Vec<TokenIndex> = records.into_iter()
                 .filter(|record| {
-                    // Match either the exact principal text or attempt to convert from AccountIdentifier
                     record.owner == owner_text
+                        || account_ids.iter().any(|id| id.eq_ignore_ascii_case(&record.owner))
                 })
                 .map(|record| record.index)
                 .collect();
@@ -134,4 +132,4 @@ pub async fn get_gg_tokens_for_owner(
         },
         Err(err) => Err(err)
     }
-} 
\ No newline at end of file
+}
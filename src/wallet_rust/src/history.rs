@@ -0,0 +1,550 @@
+use candid::{CandidType, Principal};
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, StableCell, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use crate::collections::CollectionHolding;
+use crate::csv_loader::HolderInfo;
+use crate::errors::WalletError;
+use crate::state::{
+    self, read_principal, write_principal, Memory, HISTORY_CHANGES_MEMORY_ID, HISTORY_CHANGE_INDEX_MEMORY_ID,
+    HISTORY_HOLDERS_MEMORY_ID, HISTORY_RETENTION_MEMORY_ID, HISTORY_SNAPSHOTS_MEMORY_ID, PRINCIPAL_SLOT,
+};
+
+// Collections recorded per snapshot; bounds the encoded size of HistoricCounts
+const MAX_HISTORY_COLLECTIONS: usize = 48;
+
+// Per-holder changes archived on top of one full snapshot. Past this the next change
+// archives the whole holder set again, so single refreshes can't grow history unbounded.
+const MAX_CHANGES_PER_SNAPSHOT: u64 = 5_000;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SnapshotSource {
+    Registry,
+    PerHolder,
+    CsvImport,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct SnapshotSummary {
+    pub snapshot_id: u64,
+    pub taken_at: u64,
+    pub source: SnapshotSource,
+    pub holders: u64,
+    pub total_tokens: u64,
+    // Column order of the counts stored for each holder
+    pub collections: Vec<Principal>,
+    // Holders written one at a time (refreshes, manual counts) since this snapshot. They
+    // are archived as per-holder changes and pruned together with it.
+    pub changes: Option<u64>,
+}
+
+// What a holder owned in an archived snapshot; empty holdings if they held nothing then
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct HolderAt {
+    pub snapshot_id: u64,
+    pub taken_at: u64,
+    pub holdings: Vec<CollectionHolding>,
+    pub total_count: u64,
+}
+
+// Average count over the window in thousandths of a token, so integer maths stays exact
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct WeightedHolding {
+    pub collection: Principal,
+    pub average_milli: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct WeightedHolder {
+    pub principal: Principal,
+    pub holdings: Vec<WeightedHolding>,
+    pub total_average_milli: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct WeightedHoldings {
+    pub window_start: u64,
+    pub window_end: u64,
+    // Start of the part of the window covered by archived snapshots. Time before it
+    // counts as holding nothing, so a window longer than the history lowers everyone.
+    pub covered_from: Option<u64>,
+    pub snapshots_used: u32,
+    pub holders: Vec<WeightedHolder>,
+}
+
+// Snapshots beyond either limit are pruned, oldest first, along with the per-holder
+// changes archived after them. The newest one is always kept.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct SnapshotRetention {
+    pub max_snapshots: Option<u32>,
+    pub max_age_secs: Option<u64>,
+}
+
+impl Default for SnapshotRetention {
+    fn default() -> Self {
+        SnapshotRetention { max_snapshots: Some(90), max_age_secs: None }
+    }
+}
+
+state::impl_candid_storable!(SnapshotSummary, 2048);
+state::impl_candid_storable!(SnapshotRetention, 64);
+
+// (snapshot id, holder)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct HistoryKey {
+    snapshot_id: u64,
+    holder: Principal,
+}
+
+impl Storable for HistoryKey {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = Vec::with_capacity(Self::MAX_SIZE as usize);
+        bytes.extend_from_slice(&self.snapshot_id.to_be_bytes());
+        write_principal(&mut bytes, &self.holder);
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        HistoryKey {
+            snapshot_id: u64::from_be_bytes(bytes[..8].try_into().expect("snapshot id is 8 bytes")),
+            holder: read_principal(&bytes[8..]),
+        }
+    }
+}
+
+impl BoundedStorable for HistoryKey {
+    const MAX_SIZE: u32 = 8 + PRINCIPAL_SLOT as u32;
+    const IS_FIXED_SIZE: bool = true;
+}
+
+// (holder, snapshot id), so one holder's changes can be found without a scan
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct HolderChangeKey {
+    holder: Principal,
+    snapshot_id: u64,
+}
+
+impl Storable for HolderChangeKey {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = Vec::with_capacity(Self::MAX_SIZE as usize);
+        write_principal(&mut bytes, &self.holder);
+        bytes.extend_from_slice(&self.snapshot_id.to_be_bytes());
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        HolderChangeKey {
+            holder: read_principal(&bytes[..PRINCIPAL_SLOT]),
+            snapshot_id: u64::from_be_bytes(bytes[PRINCIPAL_SLOT..].try_into().expect("snapshot id is 8 bytes")),
+        }
+    }
+}
+
+impl BoundedStorable for HolderChangeKey {
+    const MAX_SIZE: u32 = PRINCIPAL_SLOT as u32 + 8;
+    const IS_FIXED_SIZE: bool = true;
+}
+
+// A holder's counts after a single-holder write. Changes carry their own timestamp and
+// collections, since they aren't listed as snapshots.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+struct HolderChange {
+    taken_at: u64,
+    // Non-zero counts by collection; empty once the holder has nothing left
+    counts: Vec<(Principal, u64)>,
+}
+
+state::impl_candid_storable!(HolderChange, 4096);
+
+// A holder's counts in the snapshot's collection order, as LEB128 varints.
+// Most counts fit in one byte, which keeps a full snapshot small.
+#[derive(Clone, Debug, Default)]
+struct HistoricCounts(Vec<u64>);
+
+impl Storable for HistoricCounts {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = Vec::with_capacity(self.0.len());
+        for count in &self.0 {
+            let mut value = *count;
+            loop {
+                let byte = (value & 0x7f) as u8;
+                value >>= 7;
+                if value == 0 {
+                    bytes.push(byte);
+                    break;
+                }
+                bytes.push(byte | 0x80);
+            }
+        }
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let mut counts = Vec::new();
+        let (mut value, mut shift) = (0u64, 0u32);
+        for byte in bytes.iter() {
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                counts.push(value);
+                value = 0;
+                shift = 0;
+            } else {
+                shift += 7;
+            }
+        }
+        HistoricCounts(counts)
+    }
+}
+
+impl BoundedStorable for HistoricCounts {
+    const MAX_SIZE: u32 = 10 * MAX_HISTORY_COLLECTIONS as u32;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+thread_local! {
+    static SNAPSHOTS: RefCell<StableBTreeMap<u64, SnapshotSummary, Memory>> =
+        RefCell::new(StableBTreeMap::init(state::memory(HISTORY_SNAPSHOTS_MEMORY_ID)));
+
+    static HOLDERS: RefCell<StableBTreeMap<HistoryKey, HistoricCounts, Memory>> =
+        RefCell::new(StableBTreeMap::init(state::memory(HISTORY_HOLDERS_MEMORY_ID)));
+
+    // Per-holder changes by (snapshot id, holder), in the order they were made
+    static CHANGES: RefCell<StableBTreeMap<HistoryKey, HolderChange, Memory>> =
+        RefCell::new(StableBTreeMap::init(state::memory(HISTORY_CHANGES_MEMORY_ID)));
+
+    static CHANGE_INDEX: RefCell<StableBTreeMap<HolderChangeKey, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(state::memory(HISTORY_CHANGE_INDEX_MEMORY_ID)));
+
+    static RETENTION: RefCell<StableCell<SnapshotRetention, Memory>> = RefCell::new(
+        StableCell::init(state::memory(HISTORY_RETENTION_MEMORY_ID), SnapshotRetention::default())
+            .expect("Failed to init snapshot retention cell")
+    );
+}
+
+// Archive the holder set as snapshot `snapshot_id`. Snapshots are immutable, so an id
+// that is already archived is left as it is.
+pub fn record(snapshot_id: u64, taken_at: u64, source: SnapshotSource, holders: &[(Principal, HolderInfo)]) {
+    if SNAPSHOTS.with(|snapshots| snapshots.borrow().contains_key(&snapshot_id)) {
+        return;
+    }
+
+    let mut collections: Vec<Principal> = holders.iter()
+        .flat_map(|(_, info)| info.holdings().into_iter().map(|holding| holding.collection))
+        .collect();
+    collections.sort();
+    collections.dedup();
+    if collections.len() > MAX_HISTORY_COLLECTIONS {
+        ic_cdk::print(format!("Snapshot {} covers {} collections, archiving the first {}",
+            snapshot_id, collections.len(), MAX_HISTORY_COLLECTIONS));
+        collections.truncate(MAX_HISTORY_COLLECTIONS);
+    }
+
+    // Holders with nothing aren't stored; a missing row reads as zero
+    let (mut total_tokens, mut holders_stored) = (0u64, 0u64);
+    HOLDERS.with(|stored| {
+        let mut stored = stored.borrow_mut();
+        for (holder, info) in holders {
+            let holdings = info.holdings();
+            let counts: Vec<u64> = collections.iter()
+                .map(|collection| holdings.iter().filter(|h| h.collection == *collection).map(|h| h.count).sum())
+                .collect();
+            let total = counts.iter().sum::<u64>();
+            if total == 0 {
+                continue;
+            }
+            total_tokens += total;
+            holders_stored += 1;
+            stored.insert(HistoryKey { snapshot_id, holder: *holder }, HistoricCounts(counts));
+        }
+    });
+
+    SNAPSHOTS.with(|snapshots| {
+        snapshots.borrow_mut().insert(snapshot_id, SnapshotSummary {
+            snapshot_id,
+            taken_at,
+            source,
+            holders: holders_stored,
+            total_tokens,
+            collections,
+            changes: None,
+        });
+    });
+    prune(taken_at);
+}
+
+// Whether changes can go on top of the newest snapshot. If not (no history yet, or it
+// has MAX_CHANGES_PER_SNAPSHOT already) the caller archives the whole holder set.
+pub fn accepts_changes() -> bool {
+    newest().is_some_and(|summary| summary.changes.unwrap_or(0) < MAX_CHANGES_PER_SNAPSHOT)
+}
+
+// Archive single-holder writes made under `snapshot_id` as changes on top of the newest
+// snapshot. Holders whose counts match what is already archived are skipped, so a bump
+// that only touched other fields leaves history as it is.
+pub fn record_changes(snapshot_id: u64, taken_at: u64, holders: &[(Principal, Vec<CollectionHolding>)]) {
+    let Some(mut summary) = newest() else {
+        return;
+    };
+    let mut recorded = 0u64;
+    for (holder, holdings) in holders {
+        let mut counts: BTreeMap<Principal, u64> = BTreeMap::new();
+        for holding in holdings.iter().filter(|holding| holding.count > 0) {
+            *counts.entry(holding.collection).or_insert(0) += holding.count;
+        }
+        let counts: Vec<(Principal, u64)> = counts.into_iter().take(MAX_HISTORY_COLLECTIONS).collect();
+        let archived = latest_change(&summary, holder, u64::MAX)
+            .map_or_else(|| snapshot_counts(&summary, holder), |(_, change)| change.counts);
+        if counts == archived {
+            continue;
+        }
+
+        CHANGES.with(|changes| {
+            changes.borrow_mut().insert(HistoryKey { snapshot_id, holder: *holder }, HolderChange { taken_at, counts });
+        });
+        CHANGE_INDEX.with(|index| index.borrow_mut().insert(HolderChangeKey { holder: *holder, snapshot_id }, ()));
+        recorded += 1;
+    }
+
+    if recorded > 0 {
+        summary.changes = Some(summary.changes.unwrap_or(0) + recorded);
+        SNAPSHOTS.with(|snapshots| snapshots.borrow_mut().insert(summary.snapshot_id, summary));
+    }
+}
+
+pub fn list() -> Vec<SnapshotSummary> {
+    SNAPSHOTS.with(|snapshots| snapshots.borrow().iter().map(|(_, summary)| summary).collect())
+}
+
+// The holder's counts as of `timestamp` (nanoseconds): their last change at or before
+// it, or else the newest snapshot taken at or before it
+pub fn holder_at(holder: &Principal, timestamp: u64) -> Option<HolderAt> {
+    let summary = summary_at(timestamp)?;
+    let (snapshot_id, taken_at, counts) = match latest_change(&summary, holder, timestamp) {
+        Some((snapshot_id, change)) => (snapshot_id, change.taken_at, change.counts),
+        None => (summary.snapshot_id, summary.taken_at, snapshot_counts(&summary, holder)),
+    };
+
+    let holdings: Vec<CollectionHolding> = counts.into_iter()
+        .map(|(collection, count)| CollectionHolding::new(collection, count))
+        .collect();
+    Some(HolderAt {
+        snapshot_id,
+        taken_at,
+        total_count: holdings.iter().map(|holding| holding.count).sum(),
+        holdings,
+    })
+}
+
+fn newest() -> Option<SnapshotSummary> {
+    SNAPSHOTS.with(|snapshots| snapshots.borrow().last_key_value().map(|(_, summary)| summary))
+}
+
+fn summary_before(snapshot_id: u64) -> Option<SnapshotSummary> {
+    SNAPSHOTS.with(|snapshots| snapshots.borrow().iter_upper_bound(&snapshot_id).next().map(|(_, summary)| summary))
+}
+
+// The snapshot in effect at `timestamp`. Walks back from the newest, so recent
+// timestamps only touch recent snapshots.
+fn summary_at(timestamp: u64) -> Option<SnapshotSummary> {
+    let mut summary = newest()?;
+    while summary.taken_at > timestamp {
+        summary = summary_before(summary.snapshot_id)?;
+    }
+    Some(summary)
+}
+
+// The holder's non-zero counts in `summary` itself, ignoring later changes
+fn snapshot_counts(summary: &SnapshotSummary, holder: &Principal) -> Vec<(Principal, u64)> {
+    HOLDERS.with(|stored| stored.borrow().get(&HistoryKey { snapshot_id: summary.snapshot_id, holder: *holder }))
+        .map(|counts| column_counts(&summary.collections, counts))
+        .unwrap_or_default()
+}
+
+fn column_counts(collections: &[Principal], counts: HistoricCounts) -> Vec<(Principal, u64)> {
+    collections.iter().copied().zip(counts.0).filter(|(_, count)| *count > 0).collect()
+}
+
+// The holder's last change after `summary` taken at or before `timestamp`. Changes
+// after the next snapshot are newer than any timestamp `summary` is in effect for.
+fn latest_change(summary: &SnapshotSummary, holder: &Principal, timestamp: u64) -> Option<(u64, HolderChange)> {
+    let start = HolderChangeKey { holder: *holder, snapshot_id: summary.snapshot_id };
+    let end = HolderChangeKey { holder: *holder, snapshot_id: u64::MAX };
+    let ids: Vec<u64> = CHANGE_INDEX.with(|index| {
+        index.borrow().range(start..=end).map(|(key, _)| key.snapshot_id).collect()
+    });
+    CHANGES.with(|changes| {
+        let changes = changes.borrow();
+        ids.into_iter().rev()
+            .filter_map(|snapshot_id| {
+                changes.get(&HistoryKey { snapshot_id, holder: *holder }).map(|change| (snapshot_id, change))
+            })
+            .find(|(_, change)| change.taken_at <= timestamp)
+    })
+}
+
+// Time-weighted average holdings over [window_start, window_end]. Each snapshot's counts
+// apply from when it was taken until the next one; the newest applies until window_end.
+// A per-holder change replaces that holder's counts from when it was made.
+pub fn weighted_holdings(window_start: u64, window_end: u64) -> WeightedHoldings {
+    // The snapshot in effect at window_start and every one taken inside the window.
+    // Walks back from the newest, so older snapshots kept by retention aren't read.
+    let mut snapshots = Vec::new();
+    let mut next = newest();
+    while let Some(summary) = next {
+        next = if summary.taken_at > window_start { summary_before(summary.snapshot_id) } else { None };
+        if summary.taken_at < window_end {
+            snapshots.push(summary);
+        }
+    }
+    snapshots.reverse();
+    let window_len = window_end.saturating_sub(window_start).max(1) as u128;
+
+    // What each holder has held since when, while replaying the window
+    let mut held: BTreeMap<Principal, (Vec<(Principal, u64)>, u64)> = BTreeMap::new();
+    let mut token_nanos: BTreeMap<(Principal, Principal), u128> = BTreeMap::new();
+    let mut covered_from = None;
+    let mut snapshots_used = 0u32;
+    for (position, summary) in snapshots.iter().enumerate() {
+        let from = summary.taken_at.max(window_start);
+        let next = snapshots.get(position + 1);
+        let until = next.map_or(window_end, |next| next.taken_at).min(window_end);
+        if until > from {
+            covered_from.get_or_insert(from);
+            snapshots_used += 1;
+        }
+
+        // A snapshot replaces everyone's counts
+        for (holder, (counts, since)) in std::mem::take(&mut held) {
+            accrue(&mut token_nanos, holder, &counts, since, from);
+        }
+        let start = HistoryKey { snapshot_id: summary.snapshot_id, holder: Principal::management_canister() };
+        HOLDERS.with(|stored| {
+            for (key, counts) in stored.borrow().range(start..).take_while(|(key, _)| key.snapshot_id == summary.snapshot_id) {
+                held.insert(key.holder, (column_counts(&summary.collections, counts), from));
+            }
+        });
+
+        // Then the changes made before the next snapshot, oldest first
+        let next_id = next.map_or(u64::MAX, |next| next.snapshot_id);
+        let start = HistoryKey { snapshot_id: summary.snapshot_id + 1, holder: Principal::management_canister() };
+        CHANGES.with(|changes| {
+            let changes = changes.borrow();
+            let in_range = changes.range(start..)
+                .take_while(|(key, change)| key.snapshot_id < next_id && change.taken_at < window_end);
+            for (key, change) in in_range {
+                let at = change.taken_at.max(window_start);
+                if let Some((counts, since)) = held.remove(&key.holder) {
+                    accrue(&mut token_nanos, key.holder, &counts, since, at);
+                }
+                held.insert(key.holder, (change.counts, at));
+            }
+        });
+    }
+    for (holder, (counts, since)) in held {
+        accrue(&mut token_nanos, holder, &counts, since, window_end);
+    }
+
+    let mut holders: Vec<WeightedHolder> = Vec::new();
+    for ((principal, collection), nanos) in token_nanos {
+        let average_milli = (nanos * 1000 / window_len) as u64;
+        if holders.last().map(|holder| holder.principal) != Some(principal) {
+            holders.push(WeightedHolder { principal, holdings: Vec::new(), total_average_milli: 0 });
+        }
+        let holder = holders.last_mut().expect("pushed above");
+        holder.holdings.push(WeightedHolding { collection, average_milli });
+        holder.total_average_milli += average_milli;
+    }
+
+    WeightedHoldings { window_start, window_end, covered_from, snapshots_used, holders }
+}
+
+// Add `counts` held from `since` to `until` to the holder's token-nanoseconds
+fn accrue(
+    token_nanos: &mut BTreeMap<(Principal, Principal), u128>,
+    holder: Principal,
+    counts: &[(Principal, u64)],
+    since: u64,
+    until: u64,
+) {
+    let duration = until.saturating_sub(since) as u128;
+    if duration == 0 {
+        return;
+    }
+    for (collection, count) in counts {
+        *token_nanos.entry((holder, *collection)).or_insert(0) += *count as u128 * duration;
+    }
+}
+
+pub fn retention() -> SnapshotRetention {
+    RETENTION.with(|retention| retention.borrow().get().clone())
+}
+
+pub fn set_retention(retention: SnapshotRetention, now: u64) -> Result<SnapshotRetention, WalletError> {
+    if retention.max_snapshots == Some(0) {
+        return Err(WalletError::InvalidArgument("max_snapshots must be at least 1".to_string()));
+    }
+    RETENTION.with(|cell| cell.borrow_mut().set(retention.clone()).expect("Failed to write snapshot retention"));
+    prune(now);
+    Ok(retention)
+}
+
+fn prune(now: u64) {
+    let retention = retention();
+    let ids: Vec<(u64, u64)> = SNAPSHOTS.with(|snapshots| {
+        snapshots.borrow().iter().map(|(id, summary)| (id, summary.taken_at)).collect()
+    });
+    let keep_from = match retention.max_snapshots {
+        Some(max) => ids.len().saturating_sub(max as usize),
+        None => 0,
+    };
+    let cutoff = retention.max_age_secs.map(|secs| now.saturating_sub(secs.saturating_mul(NANOS_PER_SEC)));
+
+    // ids are in ascending order; the last entry is never pruned
+    for (position, (snapshot_id, taken_at)) in ids.iter().enumerate() {
+        let Some((next_id, _)) = ids.get(position + 1) else {
+            break;
+        };
+        let too_many = position < keep_from;
+        let too_old = cutoff.is_some_and(|cutoff| *taken_at < cutoff);
+        if too_many || too_old {
+            remove_snapshot(*snapshot_id, *next_id);
+        }
+    }
+}
+
+// Drop a snapshot and the changes archived between it and the next one
+fn remove_snapshot(snapshot_id: u64, next_id: u64) {
+    let start = HistoryKey { snapshot_id: snapshot_id + 1, holder: Principal::management_canister() };
+    CHANGES.with(|changes| {
+        let mut changes = changes.borrow_mut();
+        let keys: Vec<HistoryKey> = changes.range(start..)
+            .take_while(|(key, _)| key.snapshot_id < next_id)
+            .map(|(key, _)| key)
+            .collect();
+        CHANGE_INDEX.with(|index| {
+            let mut index = index.borrow_mut();
+            for key in keys {
+                changes.remove(&key);
+                index.remove(&HolderChangeKey { holder: key.holder, snapshot_id: key.snapshot_id });
+            }
+        });
+    });
+
+    let start = HistoryKey { snapshot_id, holder: Principal::management_canister() };
+    HOLDERS.with(|stored| {
+        let mut stored = stored.borrow_mut();
+        let keys: Vec<HistoryKey> = stored.range(start..)
+            .take_while(|(key, _)| key.snapshot_id == snapshot_id)
+            .map(|(key, _)| key)
+            .collect();
+        for key in keys {
+            stored.remove(&key);
+        }
+    });
+    SNAPSHOTS.with(|snapshots| snapshots.borrow_mut().remove(&snapshot_id));
+}
@@ -1,10 +1,10 @@
 use candid::{CandidType, Nat, Principal};
 use ic_cdk_macros::*;
 use serde::{Deserialize, Serialize};
-use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::Bound;
 use ic_cdk::api::time;
-use sha2::{Digest, Sha224};
+use sha2::Digest;
 use ic_cdk::api::call::RejectionCode;
 use ic_cdk::api::{
     management_canister::http_request::{HttpResponse, TransformArgs},
@@ -18,13 +18,48 @@ mod gg_album_interface;
 mod nft_registry_interface;
 mod gg_registry_interface;
 mod csv_loader;
+mod csv_reader;
+mod state;
+mod errors;
+mod access;
+mod collections;
+mod account_index;
+mod refresh;
+mod scheduler;
+mod refresh_job;
+mod retry;
+mod tokens;
+mod data_quality;
+mod history;
+mod rewards;
+mod exclusions;
+mod recipients;
+mod links;
+mod self_refresh;
+mod csv_import;
 
 use ext::tokens::{create_tokens_query_encodings, decode_tokens_response, QueryLog};
 use daku_interface::get_tokens_for_user;
 use gg_album_interface::get_album_tokens_for_user;
 use nft_registry_interface::{TokenOwner, DakuRegistryRecord, get_registry_raw, get_registry_tokens, get_registry_map, get_registry_entries, get_registry_daku_records};
 use gg_registry_interface::{GGRegistryRecord, get_gg_registry_raw, get_gg_registry_records, get_gg_registry_tokens, get_gg_registry_map, get_gg_tokens_for_owner};
-use csv_loader::{load_all_holders, HolderInfo};
+use csv_loader::{CsvImportReport, HolderInfo, ParsedHolderCsv};
+use csv_import::{CollectionImportReport, ImportMode, ImportProgress};
+use data_quality::{CollectionQuality, DataQualityReport, QualitySource};
+use history::{HolderAt, SnapshotRetention, SnapshotSource, SnapshotSummary, WeightedHoldings};
+use rewards::{HolderTokens, RewardBasis, RewardPlan, RewardPolicy};
+use exclusions::{Exclusion, ExclusionConfig, ExclusionReason};
+use recipients::{Account, RecipientChange};
+use links::{LinkProposal, LinkedWallets};
+use self_refresh::SelfRefreshConfig;
+use errors::WalletError;
+use access::{require_admin, InitArgs};
+use account_index::AccountIndexStats;
+use refresh::RegistryRefreshReport;
+use scheduler::{RefreshMode, RefreshOutcome, RefreshSchedule, RefreshTrigger};
+use refresh_job::RefreshJob;
+use collections::{Collection, CollectionHolding, CollectionStandard, StaleHolding, GG_ALBUM_CANISTER};
+use state::{StablePrincipal, BALANCES, NFT_COUNTS, HOLDER_INFO, KNOWN_HOLDERS, STAGED_HOLDER_INFO, DAKU_CSV_DATA, GG_CSV_DATA};
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Default, Debug)]
 struct NFTProgress {
@@ -40,63 +75,600 @@ struct NFTRecord {
     metadata: Vec<(String, String)>,
 }
 
-// Constants for external canister IDs
-const DAKU_MOTOKO_CANISTER: &str = "erfen-7aaaa-aaaap-ahniq-cai";
-const GG_ALBUM_CANISTER: &str = "v2ekv-yyaaa-aaaag-qjw2q-cai";
+// Collection canister IDs now live in the collection registry (collections.rs)
 const CACHE_DURATION: u64 = 24 * 60 * 60 * 1_000_000_000; // 24 hours in nanoseconds (optimized from 5 minutes)
 const EXT_METHOD_NAME: &str = "tokens"; // Standard EXT method for querying tokens
 
-thread_local! {
-    static BALANCES: RefCell<HashMap<Principal, u64>> = RefCell::new(HashMap::new());
-    static NFT_COUNTS: RefCell<HashMap<Principal, NFTProgress>> = RefCell::new(HashMap::new());
-    static HOLDER_INFO: RefCell<HashMap<Principal, HolderInfo>> = RefCell::new(HashMap::new());
-    static LAST_BULK_UPDATE: RefCell<u64> = RefCell::new(0);
-    // We'll keep known holders as fallback but prioritize real data
-    static KNOWN_HOLDERS: RefCell<HashMap<Principal, HolderInfo>> = RefCell::default();
-    
-    // Store CSV data for holders
-    static DAKU_CSV_DATA: RefCell<String> = RefCell::new(String::new());
-    static GG_CSV_DATA: RefCell<String> = RefCell::new(String::new());
-    static CSV_DATA_LOADED: RefCell<bool> = RefCell::new(false);
+// Holder state lives in stable memory (see state.rs), so upgrades only need to
+// check the schema version - there is nothing to serialize in pre_upgrade.
+#[init]
+fn init(args: Option<InitArgs>) {
+    state::init_schema();
+    access::apply_init_args(args);
+    scheduler::arm();
+}
+
+#[post_upgrade]
+fn post_upgrade(args: Option<InitArgs>) {
+    state::migrate_schema();
+    access::apply_init_args(args);
+    // Timers are dropped on upgrade, so re-arm the refresh schedule and any running job
+    scheduler::arm();
+    refresh_job::resume_after_upgrade();
+    account_index::resume_after_upgrade();
+}
+
+// Grant admin rights to another principal
+#[update]
+fn add_admin(principal: Principal) -> Result<(), WalletError> {
+    require_admin()?;
+    access::insert_admin(principal);
+    ic_cdk::print(format!("Admin {} added by {}", principal, ic_cdk::caller()));
+    Ok(())
+}
+
+// Revoke admin rights; controllers keep access regardless
+#[update]
+fn remove_admin(principal: Principal) -> Result<bool, WalletError> {
+    require_admin()?;
+    let removed = access::delete_admin(&principal);
+    ic_cdk::print(format!("Admin {} removed by {}: {}", principal, ic_cdk::caller(), removed));
+    Ok(removed)
+}
+
+#[query]
+fn get_admins() -> Vec<Principal> {
+    access::list_admins()
+}
+
+#[query]
+fn is_admin(principal: Principal) -> bool {
+    access::is_admin(&principal)
+}
+
+// Register a new collection or replace the settings of an existing one
+#[update]
+fn upsert_collection(collection: Collection) -> Result<(), WalletError> {
+    require_admin()?;
+    let (name, canister_id) = (collection.name.clone(), collection.canister_id);
+    collections::upsert_collection(collection)?;
+    ic_cdk::print(format!("Collection {} ({}) saved", name, canister_id));
+    Ok(())
+}
+
+// Enable or disable a collection without losing its configuration
+#[update]
+fn set_collection_enabled(canister_id: Principal, enabled: bool) -> Result<Collection, WalletError> {
+    require_admin()?;
+    let mut collection = collections::get_collection(&canister_id)
+        .ok_or(WalletError::CollectionNotFound(canister_id))?;
+    collection.enabled = enabled;
+    collections::upsert_collection(collection.clone())?;
+    Ok(collection)
+}
+
+#[update]
+fn remove_collection(canister_id: Principal) -> Result<bool, WalletError> {
+    require_admin()?;
+    tokens::replace_collection(&canister_id, &[]);
+    data_quality::forget_collection(&canister_id);
+    Ok(collections::delete_collection(&canister_id))
+}
+
+#[query]
+fn get_collections() -> Vec<Collection> {
+    collections::list_collections()
+}
+
+// Change how many subaccounts are derived per principal and rebuild the account-id index.
+// The rebuild runs in batches; rebuild_processed in the stats tracks it until it is done.
+#[update]
+fn set_subaccount_range(range: u32) -> Result<AccountIndexStats, WalletError> {
+    require_admin()?;
+    account_index::start_rebuild(range);
+    Ok(account_index::stats())
+}
+
+#[update]
+fn rebuild_account_index() -> Result<AccountIndexStats, WalletError> {
+    require_admin()?;
+    account_index::start_rebuild(account_index::get_config().subaccount_range);
+    Ok(account_index::stats())
+}
+
+#[query]
+fn get_account_index_stats() -> AccountIndexStats {
+    account_index::stats()
+}
+
+// Look up which principal an EXT AccountIdentifier belongs to
+#[query]
+fn resolve_account_id(account_id: String) -> Option<Principal> {
+    account_index::lookup(&account_id).map(|owner| owner.principal)
+}
+
+// AccountIdentifiers currently indexed for a principal
+#[query]
+fn get_account_ids_of(principal: Principal) -> Vec<String> {
+    account_index::account_ids_of(&principal)
+}
+
+// Token indices `principal` owns in `collection`, as last seen in its registry or a CSV import
+#[query]
+fn get_tokens_of(principal: Principal, collection: Principal) -> Vec<u32> {
+    tokens::tokens_of(&principal, &collection)
+}
+
+#[query]
+fn get_owner_of(collection: Principal, token_index: u32) -> Option<Principal> {
+    tokens::owner_of(&collection, token_index)
+}
+
+// Results of the consistency checks run by the last CSV import and registry refresh
+#[query]
+fn get_data_quality_report() -> DataQualityReport {
+    data_quality::report()
+}
+
+// Next `limit` principals after `cursor` among everyone we hold data for, in key order.
+// Used to walk the holders when rebuilding the account-id index.
+fn known_principals_after(cursor: Option<Principal>, limit: usize) -> Vec<Principal> {
+    let start = match cursor {
+        Some(cursor) => Bound::Excluded(StablePrincipal(cursor)),
+        None => Bound::Unbounded,
+    };
+    let mut batch: BTreeSet<Principal> = holder_batch_after(cursor, limit).into_iter().collect();
+    BALANCES.with(|balances| {
+        batch.extend(balances.borrow().range((start, Bound::Unbounded)).take(limit).map(|(k, _)| k.0));
+    });
+    batch.into_iter().take(limit).collect()
 }
 
 // Default HolderInfo function
 fn default_holder_info() -> HolderInfo {
-    HolderInfo {
-        daku_count: 0,
-        gg_count: 0,
-        total_count: 0,
-        last_updated: time(),
-    }
+    HolderInfo::from_holdings(Vec::new(), time())
 }
 
-// Load CSV data into the canister
+// Load CSV data into the canister. Bad rows are skipped and listed in the report;
+// a file that can't be parsed at all is rejected with CsvParse.
 #[update]
-fn load_csv_data(daku_csv: String, gg_csv: String) -> bool {
+fn load_csv_data(daku_csv: String, gg_csv: String) -> Result<CsvImportReport, WalletError> {
+    require_admin()?;
+    apply_csv_data(daku_csv, gg_csv)
+}
+
+// Load test CSV data for development
+#[update]
+fn load_test_csv_data() -> Result<CsvImportReport, WalletError> {
+    require_admin()?;
+    let (daku_csv, gg_csv) = csv_loader::generate_test_csv_data();
+    apply_csv_data(daku_csv, gg_csv)
+}
+
+// Parse both CSV exports and replace the holder snapshot with them
+fn apply_csv_data(daku_csv: String, gg_csv: String) -> Result<CsvImportReport, WalletError> {
     ic_cdk::print("Loading CSV data...");
     
-    // Store the CSV data
-    DAKU_CSV_DATA.with(|data| {
-        *data.borrow_mut() = daku_csv.clone();
+    // Parse and load the data
+    let daku = csv_loader::parse_holder_csv(&daku_csv)?;
+    let gg = csv_loader::parse_holder_csv(&gg_csv)?;
+    let (holders, report) = csv_loader::merge_holder_csvs(&daku, &gg);
+    
+    let current_time = time();
+    for (collection, parsed) in [(collections::daku_canister(), &daku), (collections::gg_canister(), &gg)] {
+        data_quality::record(&check_csv_quality(collection, parsed, &parsed.counts, false, current_time));
+    }
+    let bindings: Vec<(String, Principal)> = daku.bindings.iter().chain(gg.bindings.iter()).cloned().collect();
+    data_quality::check_account_bindings(&bindings);
+    
+    // Index holder accounts so registry owners can be mapped back to principals
+    account_index::index_principals(holders.keys());
+    for (account_id, principal) in daku.bindings.iter().chain(gg.bindings.iter()) {
+        account_index::bind_account(account_id, *principal);
+    }
+    
+    for (collection, parsed) in [(collections::daku_canister(), &daku), (collections::gg_canister(), &gg)] {
+        if let Some(owners) = token_owners(parsed) {
+            tokens::replace_collection(&collection, &owners);
+        }
+    }
+    
+    // The parsed snapshot is what we keep; drop raw files stored by older versions
+    state::set_stable_string(&DAKU_CSV_DATA, String::new());
+    state::set_stable_string(&GG_CSV_DATA, String::new());
+    
+    // Store the parsed data
+    store_holder_snapshot(&holders, current_time);
+    archive_holders(SnapshotSource::CsvImport);
+    
+    // Mark data as loaded
+    state::update_meta(|meta| meta.csv_data_loaded = true);
+    
+    ic_cdk::print(format!("Loaded data for {} holders ({} Daku / {} GG rows rejected)",
+        holders.len(), report.daku.rejected_rows, report.gg.rejected_rows));
+    Ok(report)
+}
+
+// Start a chunked upload of one collection's holder export. Chunks are sent with
+// append_chunk and the import is applied by commit_import once the hash matches.
+#[update]
+fn begin_import(collection: Principal) -> Result<u64, WalletError> {
+    require_admin()?;
+    if collections::get_collection(&collection).is_none() {
+        return Err(WalletError::CollectionNotFound(collection));
+    }
+    csv_import::begin(collection)
+}
+
+#[update]
+fn append_chunk(session: u64, bytes: Vec<u8>) -> Result<ImportProgress, WalletError> {
+    require_admin()?;
+    csv_import::append(session, bytes)
+}
+
+// Verify the upload against `sha256` (hex) and apply it to the collection. A DryRun
+// leaves the session open so the same upload can then be committed for real.
+#[update]
+fn commit_import(session: u64, sha256: String, mode: ImportMode) -> Result<CollectionImportReport, WalletError> {
+    require_admin()?;
+    refresh_job::ensure_idle()?;
+    let (collection, sha256, parsed) = csv_import::commit(session, &sha256, mode == ImportMode::DryRun)?;
+    Ok(apply_collection_import(collection, sha256, parsed, mode))
+}
+
+// Import one collection's holder export in a single message, for files well under
+// the ingress limit. Larger files go through begin_import.
+#[update]
+fn import_collection_csv(collection: Principal, csv: String, mode: ImportMode) -> Result<CollectionImportReport, WalletError> {
+    require_admin()?;
+    if collections::get_collection(&collection).is_none() {
+        return Err(WalletError::CollectionNotFound(collection));
+    }
+    refresh_job::ensure_idle()?;
+    let sha256 = hex::encode(sha2::Sha256::digest(csv.as_bytes()));
+    let parsed = csv_loader::parse_holder_csv(&csv)?;
+    Ok(apply_collection_import(collection, sha256, parsed, mode))
+}
+
+// Update one collection's counts from an import, leaving every other collection as is.
+// Only holders whose count for the collection changes are rewritten.
+fn apply_collection_import(collection: Principal, sha256: String, parsed: ParsedHolderCsv, mode: ImportMode) -> CollectionImportReport {
+    let current: HashMap<Principal, HolderInfo> = HOLDER_INFO.with(|holder_info| {
+        holder_info.borrow().iter().map(|(k, info)| (k.0, info)).collect()
     });
+    let before: HashMap<Principal, u64> = current.iter()
+        .map(|(principal, info)| {
+            let count = info.holdings().iter()
+                .filter(|holding| holding.collection == collection)
+                .map(|holding| holding.count)
+                .sum::<u64>();
+            (*principal, count)
+        })
+        .filter(|(_, count)| *count > 0)
+        .collect();
+    let after: HashMap<Principal, u64> = match mode {
+        ImportMode::Replace | ImportMode::DryRun => parsed.counts.clone(),
+        ImportMode::Merge => {
+            let mut merged = before.clone();
+            merged.extend(parsed.counts.iter().map(|(principal, count)| (*principal, *count)));
+            merged
+        },
+    };
+    let diff = csv_import::diff_counts(&before, &after);
+    let apply = mode != ImportMode::DryRun;
+    let current_time = time();
+    let quality = check_csv_quality(collection, &parsed, &after, mode == ImportMode::Merge, current_time);
+    
+    if apply {
+        data_quality::record(&quality);
+        data_quality::check_account_bindings(&parsed.bindings);
+        account_index::index_principals(parsed.counts.keys());
+        for (account_id, principal) in &parsed.bindings {
+            account_index::bind_account(account_id, *principal);
+        }
+    }
+    
+    let mut holders = current.len() as u64;
+    let mut changed: Vec<Principal> = before.keys().chain(after.keys())
+        .filter(|principal| before.get(principal) != after.get(principal))
+        .copied()
+        .collect();
+    changed.sort();
+    changed.dedup();
+    for principal in &changed {
+        let mut holdings = current.get(principal).map(|info| info.holdings()).unwrap_or_default();
+        holdings.retain(|holding| holding.collection != collection);
+        holdings.push(CollectionHolding::new(collection, after.get(principal).copied().unwrap_or(0)));
+        let info = HolderInfo::from_holdings(holdings, current_time);
+        let empty = info.collections.as_ref().is_none_or(|holdings| holdings.is_empty());
+        
+        match (current.contains_key(principal), empty) {
+            (false, false) => holders += 1,
+            (true, true) => holders -= 1,
+            _ => {},
+        }
+        if !apply {
+            continue;
+        }
+        if empty {
+            remove_holder(*principal, current_time);
+        } else {
+            put_holder(*principal, &info, current_time);
+        }
+    }
     
-    GG_CSV_DATA.with(|data| {
-        *data.borrow_mut() = gg_csv.clone();
+    if apply {
+        match (mode, &parsed.tokens) {
+            (_, None) => {},
+            (ImportMode::Merge, Some(listed)) => {
+                for (principal, indices) in listed {
+                    tokens::replace_holder_tokens(&collection, principal, indices);
+                }
+            },
+            _ => {
+                let owners = token_owners(&parsed).unwrap_or_default();
+                tokens::replace_collection(&collection, &owners);
+            },
+        }
+        if !changed.is_empty() {
+            state::bump_snapshot(current_time);
+            archive_holders(SnapshotSource::CsvImport);
+        }
+        state::update_meta(|meta| meta.csv_data_loaded = true);
+    }
+    
+    ic_cdk::print(format!("{:?} import for collection {}: {} rows ({} rejected), +{} -{} ~{} holders",
+        mode, collection, parsed.report.accepted_rows, parsed.report.rejected_rows,
+        diff.added_holders, diff.removed_holders, diff.changed_holders));
+    CollectionImportReport {
+        collection,
+        mode,
+        sha256,
+        file: parsed.report,
+        diff,
+        quality,
+        holders,
+    }
+}
+
+// Run the data-quality checks on one collection's export. `counts` are the holder counts
+// the import results in; a merge also checks listed tokens against their stored owners.
+fn check_csv_quality(collection: Principal, parsed: &ParsedHolderCsv, counts: &HashMap<Principal, u64>, merge: bool, current_time: u64) -> CollectionQuality {
+    let mut owners = token_owners(parsed).unwrap_or_default();
+    let listed_supply = parsed.tokens.as_ref()
+        .map(|_| owners.iter().map(|(index, _)| *index).collect::<HashSet<u32>>().len() as u64)
+        .filter(|supply| *supply > 0);
+    let supply = data_quality::registry_supply(&collection).or(listed_supply);
+    
+    if merge {
+        let stored: Vec<(u32, Principal)> = owners.iter()
+            .filter_map(|(index, _)| tokens::owner_of(&collection, *index).map(|owner| (*index, owner)))
+            .filter(|(_, owner)| !parsed.counts.contains_key(owner))
+            .collect();
+        owners.extend(stored);
+    }
+    data_quality::check_collection(collection, QualitySource::CsvImport, &owners, counts, supply, current_time)
+}
+
+// (token, owner) pairs from the tokenIds column of a parsed export, if it had one
+fn token_owners(parsed: &ParsedHolderCsv) -> Option<Vec<(u32, Principal)>> {
+    let listed = parsed.tokens.as_ref()?;
+    Some(listed.iter()
+        .flat_map(|(owner, indices)| indices.iter().map(move |index| (*index, *owner)))
+        .collect())
+}
+
+// Function to update all holder information.
+// Returns the number of holders refreshed from CSV, or queued for the per-holder refresh job.
+#[update]
+fn update_all_holders() -> Result<u64, WalletError> {
+    require_admin()?;
+    start_holder_refresh(RefreshTrigger::Manual)
+}
+
+// Keep the imported CSV snapshot as is, or start a batched per-holder refresh job
+fn start_holder_refresh(trigger: RefreshTrigger) -> Result<u64, WalletError> {
+    let current_time = time();
+    
+    // Check if CSV data is loaded
+    let csv_loaded = state::get_meta().csv_data_loaded;
+    
+    if csv_loaded {
+        refresh_job::ensure_idle()?;
+        
+        // Imports are applied to HOLDER_INFO when committed, so there is nothing to
+        // re-read; external canisters aren't queried while CSV data is in use
+        let holders = HOLDER_INFO.with(|holder_info| holder_info.borrow().len());
+        
+        ic_cdk::print(format!("Holder data for {} holders comes from CSV imports", holders));
+        scheduler::record_run(trigger, RefreshMode::PerHolder, current_time, RefreshOutcome::Ok { holders, tokens_unresolved: None });
+        return Ok(holders);
+    }
+    
+    // Log the start of the operation
+    ic_cdk::print(format!("Starting update_all_holders at timestamp: {}", current_time));
+    
+    // Add known principals for a more complete update
+    ensure_known_holders();
+    let total = holder_batch_after(None, usize::MAX).len() as u64;
+    
+    refresh_job::start_holder_job(trigger, total)?;
+    Ok(total)
+}
+
+// Next `limit` principals after `cursor`, in key order, from HOLDER_INFO and KNOWN_HOLDERS
+fn holder_batch_after(cursor: Option<Principal>, limit: usize) -> Vec<Principal> {
+    let start = match cursor {
+        Some(cursor) => Bound::Excluded(StablePrincipal(cursor)),
+        None => Bound::Unbounded,
+    };
+    let mut batch: BTreeSet<Principal> = BTreeSet::new();
+    for map in [&HOLDER_INFO, &KNOWN_HOLDERS] {
+        map.with(|map| {
+            batch.extend(map.borrow().range((start, Bound::Unbounded)).take(limit).map(|(k, _)| k.0));
+        });
+    }
+    batch.into_iter().take(limit).collect()
+}
+
+// Query one batch of holders for the refresh job and stage the results; returns how many were updated.
+// HOLDER_INFO is left alone until the whole job commits.
+async fn refresh_holder_batch(principals: &[Principal]) -> u64 {
+    let mut updated_count = 0;
+    
+    account_index::index_principals(principals);
+    
+    // Update each principal
+    for principal in principals {
+        if let Ok(info) = update_holder_info(principal).await {
+            STAGED_HOLDER_INFO.with(|staged| {
+                staged.borrow_mut().insert(StablePrincipal(*principal), info);
+            });
+            updated_count += 1;
+        }
+    }
+    
+    updated_count
+}
+
+fn clear_staged_holders() {
+    STAGED_HOLDER_INFO.with(|staged| {
+        let mut staged = staged.borrow_mut();
+        let keys: Vec<StablePrincipal> = staged.iter().map(|(k, _)| k).collect();
+        for key in keys {
+            staged.remove(&key);
+        }
     });
+}
+
+// Swap a finished job's results into HOLDER_INFO. Runs without awaiting, so readers
+// see either the previous snapshot or the new one, never a mix.
+fn commit_staged_holders(current_time: u64) -> u64 {
+    let staged: Vec<(StablePrincipal, HolderInfo)> = STAGED_HOLDER_INFO.with(|staged| staged.borrow().iter().collect());
+    for (key, info) in &staged {
+        put_holder(key.0, info, current_time);
+    }
+    clear_staged_holders();
     
-    // Parse and load the data
-    let holders = load_all_holders(&daku_csv, &gg_csv);
+    state::update_meta(|meta| meta.last_bulk_update = current_time);
+    let snapshot_id = state::bump_snapshot(current_time);
+    archive_holders(SnapshotSource::PerHolder);
+    ic_cdk::print(format!("Committed {} staged holders as snapshot {}", staged.len(), snapshot_id));
+    staged.len() as u64
+}
+
+// Rebuild every holder from one getRegistry call per enabled collection.
+// Owners listed as principals are picked up even if we have never seen them, but EXT
+// AccountIdentifiers only resolve for principals already in the account-id index.
+// Tokens of other accounts are left out and counted in tokens_unresolved; the report
+// lists those accounts so they can be bound or their principals indexed.
+#[update]
+async fn refresh_holders_from_registry() -> Result<RegistryRefreshReport, WalletError> {
+    require_admin()?;
+    refresh_job::run_registry(RefreshTrigger::Manual).await
+}
+
+async fn run_registry_refresh() -> RegistryRefreshReport {
+    let current_time = time();
     
-    // Store the parsed data
-    HOLDER_INFO.with(|holder_info| {
-        *holder_info.borrow_mut() = holders.clone();
+    let previous: HashMap<Principal, HolderInfo> = HOLDER_INFO.with(|holder_info| {
+        holder_info.borrow().iter().map(|(k, v)| (k.0, v)).collect()
     });
     
+    let snapshot = refresh::build_registry_snapshot(
+        &collections::enabled_collections(),
+        &previous,
+        current_time,
+    ).await;
+    let report = snapshot.report;
+    
+    store_holder_snapshot(&snapshot.holders, current_time);
+    for (collection, owners) in &snapshot.tokens {
+        tokens::replace_collection(collection, owners);
+        
+        let counts: HashMap<Principal, u64> = snapshot.holders.iter()
+            .map(|(principal, info)| {
+                let count = info.holdings().iter()
+                    .filter(|holding| holding.collection == *collection)
+                    .map(|holding| holding.count)
+                    .sum::<u64>();
+                (*principal, count)
+            })
+            .collect();
+        let supply = report.collections.iter()
+            .find(|read| read.collection == *collection)
+            .map(|read| read.tokens_attributed + read.tokens_unresolved);
+        data_quality::record(&data_quality::check_collection(
+            *collection, QualitySource::Registry, owners, &counts, supply, current_time,
+        ));
+    }
+    
+    archive_holders(SnapshotSource::Registry);
+    
+    // Live registry data now supersedes any uploaded CSV
+    state::update_meta(|meta| meta.csv_data_loaded = false);
+    
+    ic_cdk::print(format!("Registry refresh: {} holders, {} tokens attributed, {} unresolved",
+        report.holders, report.tokens_attributed, report.tokens_unresolved));
+    report
+}
+
+// Configure the automatic refresh; 0 disables it. Also resumes a paused schedule.
+#[update]
+fn set_refresh_interval(interval_secs: u64, mode: Option<RefreshMode>) -> Result<RefreshSchedule, WalletError> {
+    require_admin()?;
+    let allowed = scheduler::MIN_REFRESH_INTERVAL_SECS..=scheduler::MAX_REFRESH_INTERVAL_SECS;
+    if interval_secs != 0 && !allowed.contains(&interval_secs) {
+        return Err(WalletError::InvalidArgument(format!(
+            "interval_secs must be 0 or between {} and {}",
+            scheduler::MIN_REFRESH_INTERVAL_SECS, scheduler::MAX_REFRESH_INTERVAL_SECS
+        )));
+    }
+    scheduler::set_interval(interval_secs, mode);
+    Ok(scheduler::get_schedule())
+}
+
+#[update]
+fn pause_refresh() -> Result<RefreshSchedule, WalletError> {
+    require_admin()?;
+    scheduler::pause();
+    Ok(scheduler::get_schedule())
+}
+
+#[query]
+fn get_refresh_schedule() -> RefreshSchedule {
+    scheduler::get_schedule()
+}
+
+// Status and progress of the current (or most recent) holder refresh
+#[query]
+fn get_refresh_job() -> Option<RefreshJob> {
+    refresh_job::current_job()
+}
+
+// Stop a running refresh after its current batch. Nothing it staged is committed, so
+// HOLDER_INFO keeps the last completed snapshot.
+#[update]
+fn cancel_refresh_job() -> Result<Option<RefreshJob>, WalletError> {
+    require_admin()?;
+    Ok(refresh_job::cancel())
+}
+
+// Continue an interrupted per-holder refresh from its cursor
+#[update]
+fn resume_refresh_job() -> Result<RefreshJob, WalletError> {
+    require_admin()?;
+    refresh_job::resume()
+}
+
+// Replace HOLDER_INFO with a complete snapshot and mirror the totals into NFT_COUNTS
+fn store_holder_snapshot(holders: &HashMap<Principal, HolderInfo>, current_time: u64) {
+    replace_holder_info(holders);
+    
     // Update NFT_COUNTS for compatibility
-    let current_time = time();
     for (principal, info) in holders.iter() {
         NFT_COUNTS.with(|counts| {
-            counts.borrow_mut().insert(*principal, NFTProgress {
+            counts.borrow_mut().insert(StablePrincipal(*principal), NFTProgress {
                 count: info.total_count,
                 in_progress: false,
                 last_updated: current_time,
@@ -104,187 +676,495 @@ fn load_csv_data(daku_csv: String, gg_csv: String) -> bool {
         });
     }
     
-    // Mark data as loaded
-    CSV_DATA_LOADED.with(|loaded| {
-        *loaded.borrow_mut() = true;
+    state::update_meta(|meta| meta.last_bulk_update = current_time);
+    state::bump_snapshot(current_time);
+}
+
+// Keep the current HOLDER_INFO as an immutable snapshot for get_holder_at
+fn archive_holders(source: SnapshotSource) {
+    let meta = state::get_meta();
+    let (Some(snapshot_id), Some(taken_at)) = (meta.snapshot_id, meta.snapshot_at) else {
+        return;
+    };
+    let holders: Vec<(Principal, HolderInfo)> = HOLDER_INFO.with(|holder_info| {
+        holder_info.borrow().iter().map(|(k, v)| (k.0, v)).collect()
     });
-    
-    LAST_BULK_UPDATE.with(|last_update| {
-        *last_update.borrow_mut() = current_time;
+    history::record(snapshot_id, taken_at, source, &holders);
+}
+
+// Archive the current records of `principals` after a write that bumped the snapshot
+// for just those holders. They go in as per-holder changes rather than a copy of every
+// holder, unless history has nothing to build on yet or enough changes have piled up.
+fn archive_holder_changes(principals: &[Principal]) {
+    if !history::accepts_changes() {
+        archive_holders(SnapshotSource::PerHolder);
+        return;
+    }
+    let meta = state::get_meta();
+    let (Some(snapshot_id), Some(taken_at)) = (meta.snapshot_id, meta.snapshot_at) else {
+        return;
+    };
+    let holders: Vec<(Principal, Vec<CollectionHolding>)> = HOLDER_INFO.with(|holder_info| {
+        let holder_info = holder_info.borrow();
+        principals.iter()
+            .map(|principal| {
+                let holdings = holder_info.get(&StablePrincipal(*principal)).map(|info| info.holdings());
+                (*principal, holdings.unwrap_or_default())
+            })
+            .collect()
     });
+    history::record_changes(snapshot_id, taken_at, &holders);
+}
+
+// Archived snapshots, oldest first
+#[query]
+fn list_snapshots() -> Vec<SnapshotSummary> {
+    history::list()
+}
+
+// What `principal` held in the last snapshot taken at or before `timestamp`
+// (nanoseconds since the epoch). None if no retained snapshot is that old.
+#[query]
+fn get_holder_at(principal: Principal, timestamp: u64) -> Option<HolderAt> {
+    history::holder_at(&principal, timestamp)
+}
+
+// Average holdings per principal and collection over the last `window_secs`, weighted
+// by how long each archived snapshot or holder change was current. Tokens bought just
+// before a payout only count for the time they were actually held.
+#[query]
+fn get_weighted_holdings(window_secs: u64) -> Result<WeightedHoldings, WalletError> {
+    if window_secs == 0 {
+        return Err(WalletError::InvalidArgument("window_secs must be greater than 0".to_string()));
+    }
+    let now = time();
+    let window_start = now.saturating_sub(window_secs.saturating_mul(1_000_000_000));
+    let mut weighted = history::weighted_holdings(window_start, now);
+    weighted.holders.retain(|holder| !exclusions::is_excluded(&holder.principal));
+    Ok(weighted)
+}
+
+#[query]
+fn get_reward_policy() -> RewardPolicy {
+    rewards::policy()
+}
+
+#[update]
+fn set_reward_policy(policy: RewardPolicy) -> Result<RewardPolicy, WalletError> {
+    require_admin()?;
+    let policy = rewards::set_policy(policy)?;
     
-    ic_cdk::print(format!("Loaded data for {} holders", holders.len()));
-    true
+    // Holder records show the set bonus, so bring them in line with the new rules
+    restamp_holders(|_, info| info.refresh_set_bonus(&policy));
+    Ok(policy)
 }
 
-// Load test CSV data for development
+// Rewrite the HOLDER_INFO records `f` changes (it returns true for those) and bump the
+// snapshot if there were any. Used when a setting shown in holder records changes.
+fn restamp_holders<F: FnMut(&Principal, &mut HolderInfo) -> bool>(mut f: F) {
+    let updated: Vec<(StablePrincipal, HolderInfo)> = HOLDER_INFO.with(|holder_info| {
+        holder_info.borrow().iter()
+            .filter_map(|(key, mut info)| f(&key.0, &mut info).then_some((key, info)))
+            .collect()
+    });
+    if !updated.is_empty() {
+        let principals: Vec<Principal> = updated.iter().map(|(key, _)| key.0).collect();
+        HOLDER_INFO.with(|holder_info| {
+            let mut holder_info = holder_info.borrow_mut();
+            for (key, info) in updated {
+                holder_info.insert(key, info);
+            }
+        });
+        state::bump_snapshot(time());
+        archive_holder_changes(&principals);
+    }
+}
+
+// Exclude a treasury, escrow or other wallet from rewards. It stays tracked and flagged.
 #[update]
-fn load_test_csv_data() -> bool {
-    let (daku_csv, gg_csv) = csv_loader::generate_test_csv_data();
-    load_csv_data(daku_csv, gg_csv)
+fn add_exclusion(principal: Principal, note: String) -> Result<(), WalletError> {
+    require_admin()?;
+    exclusions::add(principal, note, ic_cdk::caller())?;
+    restamp_exclusions();
+    Ok(())
+}
+
+#[update]
+fn remove_exclusion(principal: Principal) -> Result<bool, WalletError> {
+    require_admin()?;
+    let removed = exclusions::remove(&principal);
+    restamp_exclusions();
+    Ok(removed)
+}
+
+#[query]
+fn get_exclusions() -> Vec<(Principal, Exclusion)> {
+    exclusions::list()
+}
+
+#[query]
+fn get_exclusion_config() -> ExclusionConfig {
+    exclusions::config()
+}
+
+// Treat every canister principal (opaque id) as excluded
+#[update]
+fn set_auto_exclude_canisters(enabled: bool) -> Result<ExclusionConfig, WalletError> {
+    require_admin()?;
+    let config = exclusions::set_auto_exclude_canisters(enabled);
+    restamp_exclusions();
+    Ok(config)
+}
+
+#[query]
+fn get_exclusion_reason(principal: Principal) -> Option<ExclusionReason> {
+    exclusions::reason_for(&principal)
+}
+
+// Have rewards for the caller's holdings paid to `account`, e.g. a hot wallet or an
+// exchange subaccount, while the NFTs stay in a cold wallet
+#[update]
+fn set_reward_recipient(account: Account) -> Result<Account, WalletError> {
+    change_reward_recipient(Some(account))
+}
+
+// Go back to receiving rewards on the holding principal itself
+#[update]
+fn clear_reward_recipient() -> Result<Account, WalletError> {
+    change_reward_recipient(None)
+}
+
+fn change_reward_recipient(account: Option<Account>) -> Result<Account, WalletError> {
+    let holder = ic_cdk::caller();
+    if holder == Principal::anonymous() {
+        return Err(WalletError::InvalidArgument("the anonymous principal cannot delegate rewards".to_string()));
+    }
+    let changed = recipients::set(holder, account, time())?;
+    // The recipient is part of the holder data only for tracked holders
+    if changed && HOLDER_INFO.with(|holder_info| holder_info.borrow().contains_key(&StablePrincipal(holder))) {
+        state::bump_snapshot(time());
+        archive_holder_changes(&[holder]);
+    }
+    Ok(recipients::resolve(&holder))
+}
+
+#[query]
+fn get_reward_recipient(holder: Principal) -> Account {
+    recipients::resolve(&holder)
+}
+
+#[query]
+fn get_reward_recipient_history(holder: Principal) -> Vec<RecipientChange> {
+    recipients::history(&holder)
+}
+
+fn restamp_exclusions() {
+    restamp_holders(|principal, info| {
+        let excluded = exclusions::reason_for(principal);
+        let changed = excluded != info.excluded;
+        info.excluded = excluded;
+        changed
+    });
+}
+
+// Divide `pool_amount` between holders under the current reward policy. The plan hash
+// lets the payout side check it is paying out exactly the plan that was reviewed.
+#[query]
+fn compute_reward_plan(pool_amount: u64) -> RewardPlan {
+    let policy = rewards::policy();
+    let (basis, holders) = match policy.weighting_window_secs {
+        Some(window_secs) => {
+            let now = time();
+            let weighted = history::weighted_holdings(now.saturating_sub(window_secs.saturating_mul(1_000_000_000)), now);
+            let holders = weighted.holders.into_iter()
+                .map(|holder| (holder.principal, holder.holdings.iter().map(|h| (h.collection, h.average_milli)).collect()))
+                .collect();
+            (RewardBasis::TimeWeighted { window_start: weighted.window_start, window_end: weighted.window_end }, holders)
+        },
+        None => {
+            let holders = HOLDER_INFO.with(|holder_info| {
+                holder_info.borrow().iter()
+                    .map(|(k, info)| (k.0, info.holdings().iter().map(|h| (h.collection, h.count.saturating_mul(1000))).collect()))
+                    .collect()
+            });
+            (RewardBasis::Snapshot { snapshot_id: state::get_meta().snapshot_id }, holders)
+        },
+    };
+    rewards::compute_plan(policy, basis, reward_holders(holders), pool_amount)
+}
+
+// Drop excluded wallets and fold linked wallets into their primary, which is paid for
+// the whole group. An excluded primary is never paid: its members are then paid on their
+// own, as if unlinked. `holders` pairs each wallet with its (collection, thousandths) amounts.
+fn reward_holders(holders: Vec<(Principal, Vec<(Principal, u64)>)>) -> Vec<HolderTokens> {
+    let mut groups: BTreeMap<Principal, (Vec<Principal>, BTreeMap<Principal, u64>)> = BTreeMap::new();
+    for (principal, holdings) in holders {
+        if exclusions::is_excluded(&principal) {
+            continue;
+        }
+        let primary = Some(links::primary_of(&principal))
+            .filter(|primary| !exclusions::is_excluded(primary))
+            .unwrap_or(principal);
+        let (linked, totals) = groups.entry(primary).or_default();
+        if principal != primary {
+            linked.push(principal);
+        }
+        for (collection, milli) in holdings {
+            let total = totals.entry(collection).or_default();
+            *total = total.saturating_add(milli);
+        }
+    }
+    groups.into_iter()
+        .map(|(primary, (linked_wallets, totals))| HolderTokens {
+            principal: primary,
+            recipient: recipients::resolve(&primary),
+            linked_wallets,
+            holdings: totals.into_iter().collect(),
+        })
+        .collect()
+}
+
+// Ask `target` to join the caller's wallet group. Rewards for the group's holdings are
+// computed for, and paid to, the group's primary; the first proposer becomes the primary.
+// The caller's open proposals lapse if it joins or leaves a group before they are confirmed.
+#[update]
+fn propose_wallet_link(target: Principal) -> Result<LinkProposal, WalletError> {
+    links::propose(link_caller()?, target, time())
+}
+
+#[update]
+fn cancel_wallet_link(target: Principal) -> Result<bool, WalletError> {
+    Ok(links::cancel(link_caller()?, target))
+}
+
+// Called by the proposal's target to accept it
+#[update]
+fn confirm_wallet_link(proposer: Principal) -> Result<LinkedWallets, WalletError> {
+    links::confirm(link_caller()?, proposer, time())
+}
+
+// Split `wallet` off its group; callable by the wallet itself or by its primary
+#[update]
+fn unlink_wallet(wallet: Principal) -> Result<bool, WalletError> {
+    links::unlink(link_caller()?, wallet)
+}
+
+#[query]
+fn get_linked_wallets(principal: Principal) -> LinkedWallets {
+    links::linked_wallets(&principal, time())
+}
+
+fn link_caller() -> Result<Principal, WalletError> {
+    let caller = ic_cdk::caller();
+    if caller == Principal::anonymous() {
+        return Err(WalletError::InvalidArgument("the anonymous principal cannot link wallets".to_string()));
+    }
+    Ok(caller)
+}
+
+#[query]
+fn get_snapshot_retention() -> SnapshotRetention {
+    history::retention()
+}
+
+// Limit archived snapshots by count and/or age; None lifts that limit
+#[update]
+fn set_snapshot_retention(max_snapshots: Option<u32>, max_age_secs: Option<u64>) -> Result<SnapshotRetention, WalletError> {
+    require_admin()?;
+    history::set_retention(SnapshotRetention { max_snapshots, max_age_secs }, time())
+}
+
+// Write one holder to HOLDER_INFO and mirror its total into NFT_COUNTS.
+// Callers bump the snapshot id once they are done writing.
+fn put_holder(principal: Principal, info: &HolderInfo, current_time: u64) {
+    HOLDER_INFO.with(|holder_info| {
+        let info = HolderInfo { excluded: exclusions::reason_for(&principal), ..info.clone() };
+        holder_info.borrow_mut().insert(StablePrincipal(principal), info);
+    });
+    NFT_COUNTS.with(|counts| {
+        counts.borrow_mut().insert(StablePrincipal(principal), NFTProgress {
+            count: info.total_count,
+            in_progress: false,
+            last_updated: current_time,
+        });
+    });
+}
+
+// Drop a holder left without any holdings; NFT_COUNTS keeps a zero entry
+fn remove_holder(principal: Principal, current_time: u64) {
+    HOLDER_INFO.with(|holder_info| {
+        holder_info.borrow_mut().remove(&StablePrincipal(principal));
+    });
+    NFT_COUNTS.with(|counts| {
+        counts.borrow_mut().insert(StablePrincipal(principal), NFTProgress {
+            count: 0,
+            in_progress: false,
+            last_updated: current_time,
+        });
+    });
+}
+
+// Function to get all holder information
+#[query]
+fn get_all_holders() -> Vec<(Principal, HolderInfo)> {
+    payable_holders()
+}
+
+// The payout canister pays everyone the holder endpoints list, so excluded holders are
+// left out of all of them
+fn payable_holders() -> Vec<(Principal, HolderInfo)> {
+    let mut holders = all_holders();
+    holders.retain(|(principal, _)| !exclusions::is_excluded(principal));
+    holders
+}
+
+fn all_holders() -> Vec<(Principal, HolderInfo)> {
+    let mut holders = stored_or_known_holders();
+    for (principal, info) in holders.iter_mut() {
+        info.reward_recipient = Some(recipients::resolve(principal));
+    }
+    holders
 }
 
-// Function to update all holder information
-#[update]
-async fn update_all_holders() -> u64 {
-    let current_time = time();
+fn stored_or_known_holders() -> Vec<(Principal, HolderInfo)> {
+    // First check if CSV data is loaded
+    let csv_loaded = state::get_meta().csv_data_loaded;
     
-    // Check if CSV data is loaded
-    let csv_loaded = CSV_DATA_LOADED.with(|loaded| {
-        *loaded.borrow()
+    // First check if we have data in HOLDER_INFO
+    let holder_info: Vec<(Principal, HolderInfo)> = HOLDER_INFO.with(|holder_info| {
+        holder_info.borrow().iter()
+            .map(|(k, v)| (k.0, v))
+            .collect()
     });
     
-    if csv_loaded {
-        // If CSV data is loaded, use that instead of querying external canisters
-        let daku_csv = DAKU_CSV_DATA.with(|data| {
-            data.borrow().clone()
-        });
-        
-        let gg_csv = GG_CSV_DATA.with(|data| {
-            data.borrow().clone()
-        });
-        
-        // Parse and load the data
-        let holders = load_all_holders(&daku_csv, &gg_csv);
-        
-        // Store the parsed data
-        HOLDER_INFO.with(|holder_info| {
-            *holder_info.borrow_mut() = holders.clone();
-        });
-        
-        // Update NFT_COUNTS for compatibility
-        for (principal, info) in holders.iter() {
-            NFT_COUNTS.with(|counts| {
-                counts.borrow_mut().insert(*principal, NFTProgress {
-                    count: info.total_count,
-                    in_progress: false,
-                    last_updated: current_time,
-                });
-            });
-        }
-        
-        LAST_BULK_UPDATE.with(|last_update| {
-            *last_update.borrow_mut() = current_time;
-        });
-        
-        ic_cdk::print(format!("Refreshed data for {} holders from CSV", holders.len()));
-        return holders.len() as u64;
+    // Data loaded from CSV is authoritative even when empty
+    if csv_loaded || !holder_info.is_empty() {
+        return holder_info;
     }
     
-    // Log the start of the operation
-    ic_cdk::print(format!("Starting update_all_holders at timestamp: {}", current_time));
-    
-    // Get all principals to update
-    let principals = HOLDER_INFO.with(|holder_info| {
-        let info = holder_info.borrow();
-        info.keys().cloned().collect::<Vec<Principal>>()
-    });
-    
-    // Add known principals for a more complete update
-    let additional_principals = KNOWN_HOLDERS.with(|holders_ref| {
-        let mut holders = holders_ref.borrow_mut();
-        if holders.is_empty() {
-            *holders = init_known_holders();
-        }
-        holders.keys().cloned().collect::<Vec<Principal>>()
+    // If no data, use known holders (queries can't persist the seed, so build it on the fly)
+    let known_holders: Vec<(Principal, HolderInfo)> = KNOWN_HOLDERS.with(|holders_ref| {
+        holders_ref.borrow().iter()
+            .map(|(k, v)| (k.0, v))
+            .collect()
     });
     
-    // Combine and deduplicate principals
-    let mut all_principals = principals;
-    for principal in additional_principals {
-        if !all_principals.contains(&principal) {
-            all_principals.push(principal);
-        }
+    if known_holders.is_empty() {
+        // Keep the order stable for callers that index into this list
+        let mut seeded: Vec<(Principal, HolderInfo)> = init_known_holders().into_iter().collect();
+        seeded.sort_by_key(|(principal, _)| *principal);
+        seeded
+    } else {
+        known_holders
     }
-    
-    let mut updated_count = 0;
-    
-    // Update each principal
-    for principal in all_principals {
-        if let Ok(info) = update_holder_info(&principal).await {
-            HOLDER_INFO.with(|holder_info| {
-                holder_info.borrow_mut().insert(principal, info.clone());
-            });
-            
-            // Also update NFT_COUNTS for compatibility
-            NFT_COUNTS.with(|counts| {
-                counts.borrow_mut().insert(principal, NFTProgress {
-                    count: info.total_count,
-                    in_progress: false,
-                    last_updated: current_time,
-                });
-            });
-            
-            updated_count += 1;
-        }
+}
+
+// Holder data together with the snapshot it belongs to
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct HolderSnapshot {
+    pub snapshot_id: u64,
+    pub taken_at: u64,
+    pub holders: Vec<(Principal, HolderInfo)>,
+}
+
+// Same data as get_all_holders, tagged with the snapshot id and time it was committed
+#[query]
+fn get_holders_snapshot() -> HolderSnapshot {
+    let meta = state::get_meta();
+    HolderSnapshot {
+        snapshot_id: meta.snapshot_id.unwrap_or(0),
+        taken_at: meta.snapshot_at.unwrap_or(meta.last_bulk_update),
+        holders: payable_holders(),
     }
-    
-    LAST_BULK_UPDATE.with(|last_update| {
-        *last_update.borrow_mut() = current_time;
-    });
-    
-    ic_cdk::print(format!("Completed update_all_holders, updated {} holders", updated_count));
-    updated_count
 }
 
-// Function to get all holder information
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct HoldersPage {
+    pub snapshot_id: u64,
+    pub taken_at: u64,
+    pub total: u64,
+    pub holders: Vec<(Principal, HolderInfo)>,
+    // Pass back as `cursor` to get the next page; None once the last page was returned
+    pub next_cursor: Option<Principal>,
+}
+
+// Upper bound on holders per page, well below the query response size limit
+const MAX_HOLDERS_PAGE_SIZE: u32 = 1_000;
+
+// Page through holders in principal order. Pass `snapshot_id: null` for the first page and
+// the returned id afterwards; if the data changes in between, SnapshotChanged is returned.
+// Like get_all_holders it leaves out excluded holders, and `total` doesn't count them.
 #[query]
-fn get_all_holders() -> Vec<(Principal, HolderInfo)> {
-    // First check if CSV data is loaded
-    let csv_loaded = CSV_DATA_LOADED.with(|loaded| {
-        *loaded.borrow()
-    });
+fn get_holders_page(snapshot_id: Option<u64>, cursor: Option<Principal>, limit: u32) -> Result<HoldersPage, WalletError> {
+    let meta = state::get_meta();
+    let current = meta.snapshot_id.unwrap_or(0);
+    if let Some(requested) = snapshot_id {
+        if requested != current {
+            return Err(WalletError::SnapshotChanged { requested, current });
+        }
+    }
+    if limit == 0 || limit > MAX_HOLDERS_PAGE_SIZE {
+        return Err(WalletError::InvalidArgument(format!("limit must be between 1 and {}", MAX_HOLDERS_PAGE_SIZE)));
+    }
+    let limit = limit as usize;
     
-    if csv_loaded {
-        // Return data from holders loaded from CSV
+    let stored = HOLDER_INFO.with(|holder_info| holder_info.borrow().len());
+    let (holders, total) = if meta.csv_data_loaded || stored > 0 {
+        let start = match cursor {
+            Some(cursor) => Bound::Excluded(StablePrincipal(cursor)),
+            None => Bound::Unbounded,
+        };
+        // Excluded holders are skipped before the page is cut, so pages stay full.
+        // Fetch one extra entry to know whether another page follows.
         HOLDER_INFO.with(|holder_info| {
-            let info = holder_info.borrow();
-            info.iter()
-                .map(|(k, v)| (*k, v.clone()))
-                .collect()
+            let holder_info = holder_info.borrow();
+            let holders: Vec<(Principal, HolderInfo)> = holder_info.range((start, Bound::Unbounded))
+                .filter(|(k, _)| !exclusions::is_excluded(&k.0))
+                .take(limit + 1)
+                .map(|(k, v)| (k.0, HolderInfo { reward_recipient: Some(recipients::resolve(&k.0)), ..v }))
+                .collect();
+            let total = holder_info.iter().filter(|(k, _)| !exclusions::is_excluded(&k.0)).count() as u64;
+            (holders, total)
         })
     } else {
-        // First check if we have data in HOLDER_INFO
-        let holder_info = HOLDER_INFO.with(|holder_info| {
-            let info = holder_info.borrow();
-            if !info.is_empty() {
-                return info.clone();
-            }
-            HashMap::new()
-        });
-        
-        // If no data, use known holders
-        if holder_info.is_empty() {
-            KNOWN_HOLDERS.with(|holders_ref| {
-                let mut holders = holders_ref.borrow_mut();
-                if holders.is_empty() {
-                    *holders = init_known_holders();
-                }
-                holders.iter()
-                    .map(|(k, v)| (*k, v.clone()))
-                    .collect()
-            })
-        } else {
-            holder_info.iter()
-                .map(|(k, v)| (*k, v.clone()))
-                .collect()
-        }
-    }
+        // Fallback seed data is small; page it in memory with the same ordering
+        let all = payable_holders();
+        let total = all.len() as u64;
+        let holders = all.into_iter()
+            .filter(|(principal, _)| cursor.is_none_or(|cursor| *principal > cursor))
+            .take(limit + 1)
+            .collect();
+        (holders, total)
+    };
+    
+    let mut holders = holders;
+    let next_cursor = if holders.len() > limit {
+        holders.truncate(limit);
+        holders.last().map(|(principal, _)| *principal)
+    } else {
+        None
+    };
+    
+    Ok(HoldersPage {
+        snapshot_id: current,
+        taken_at: meta.snapshot_at.unwrap_or(meta.last_bulk_update),
+        total,
+        holders,
+        next_cursor,
+    })
 }
 
 // Check if we're using CSV data
 #[query]
 fn is_using_csv_data() -> bool {
-    CSV_DATA_LOADED.with(|loaded| {
-        *loaded.borrow()
-    })
+    state::get_meta().csv_data_loaded
 }
 
 // Get total number of holders in the system
 #[query]
 fn get_total_holders() -> u64 {
     HOLDER_INFO.with(|holder_info| {
-        holder_info.borrow().len() as u64
+        holder_info.borrow().len()
     })
 }
 
@@ -305,57 +1185,6 @@ struct TokensError {
     other: Option<String>,
 }
 
-// Additional types for making compatible calls to NFT canisters
-#[derive(CandidType, Debug)]
-struct AccountIdentifier {
-    hash: Vec<u8>,
-}
-
-// Convert Principal to AccountIdentifier format expected by EXT standard
-fn principal_to_account_id(principal: &Principal) -> String {
-    // Different NFT canisters might expect different formats
-    // Here we return the principal text representation which is commonly used
-    principal.to_text()
-}
-
-// Compute AccountIdentifier hash if needed by some implementations
-fn compute_account_id_hash(principal: &Principal) -> Vec<u8> {
-    let mut hasher = Sha224::new();
-    
-    // Start with \x0Aaccount-id
-    hasher.update(b"\x0Aaccount-id");
-    
-    // Add principal
-    let principal_bytes = principal.as_slice();
-    hasher.update([principal_bytes.len() as u8]);
-    hasher.update(principal_bytes);
-    
-    // Add subaccount (0 for default)
-    hasher.update([0; 32]);
-    
-    // Return the hash
-    hasher.finalize().to_vec()
-}
-
-// Prepare request arguments based on NFT canister ID
-fn prepare_tokens_args(principal: &Principal, canister_id: &str) -> Result<Vec<u8>, String> {
-    // Daku Motoko might expect a different format than GG Album
-    if canister_id == DAKU_MOTOKO_CANISTER {
-        // Try principal text format for Daku
-        candid::encode_one(principal_to_account_id(principal))
-            .map_err(|e| format!("Encoding error for Daku: {}", e))
-    } else if canister_id == GG_ALBUM_CANISTER {
-        // GG Album might expect a different format
-        // Try principal text format first
-        candid::encode_one(principal_to_account_id(principal))
-            .map_err(|e| format!("Encoding error for GG Album: {}", e))
-    } else {
-        // Default encoding
-        candid::encode_one(principal_to_account_id(principal))
-            .map_err(|e| format!("Encoding error: {}", e))
-    }
-}
-
 // Initialize known holders for development testing
 fn init_known_holders() -> HashMap<Principal, HolderInfo> {
     let current_time = time();
@@ -390,31 +1219,32 @@ fn init_known_holders() -> HashMap<Principal, HolderInfo> {
         ),
     ];
     
+    let daku_canister = collections::daku_canister();
+    let gg_canister = collections::gg_canister();
     for (principal, (daku_count, gg_count)) in test_holders {
-        holders.insert(principal, HolderInfo {
-            daku_count,
-            gg_count,
-            total_count: daku_count + gg_count,
-            last_updated: current_time,
-        });
+        holders.insert(principal, HolderInfo::from_holdings(vec![
+            CollectionHolding::new(daku_canister, daku_count),
+            CollectionHolding::new(gg_canister, gg_count),
+        ], current_time));
     }
     
     holders
 }
 
 // Improved NFT token querying with multiple fallback approaches
-async fn query_tokens(canister_id_text: &str, user: &Principal) -> Result<u64, String> {
+async fn query_tokens(canister_id_text: &str, user: &Principal) -> Result<u64, WalletError> {
     ic_cdk::print(format!("Starting robust token query for user {} on canister {}", user, canister_id_text));
     
     // Parse canister ID from text
     let canister_id = match Principal::from_text(canister_id_text) {
         Ok(id) => id,
-        Err(e) => return Err(format!("Invalid canister ID '{}': {}", canister_id_text, e)),
+        Err(_) => return Err(WalletError::InvalidPrincipal(canister_id_text.to_string())),
     };
     
     // Generate different encodings for the query
     let encodings = create_tokens_query_encodings(user);
     let mut query_logs: Vec<QueryLog> = Vec::new();
+    let mut last_rejection: Option<RejectionCode> = None;
     
     // Try each encoding format until one works
     for (encoding_name, args) in encodings {
@@ -468,8 +1298,12 @@ async fn query_tokens(canister_id_text: &str, user: &Principal) -> Result<u64, S
                 
                 // If the error is NOT_FOUND, no need to try other formats - the canister doesn't exist
                 if code == RejectionCode::DestinationInvalid {
-                    return Err(format!("DestinationInvalid - Canister {} not found", canister_id_text));
+                    return Err(WalletError::CanisterUnreachable {
+                        code,
+                        msg: format!("Canister {} not found", canister_id_text),
+                    });
                 }
+                last_rejection = Some(code);
                 
                 // Continue to try other formats
             }
@@ -483,44 +1317,59 @@ async fn query_tokens(canister_id_text: &str, user: &Principal) -> Result<u64, S
         .collect::<Vec<_>>()
         .join("; ");
         
-    Err(format!("All query formats failed for {}: {}", canister_id_text, log_summary))
+    let msg = format!("All query formats failed for {}: {}", canister_id_text, log_summary);
+    
+    // Report a rejection if any call was rejected; otherwise every reply was undecodable
+    match last_rejection {
+        Some(code) => Err(WalletError::CanisterUnreachable { code, msg }),
+        None => Err(WalletError::DecodeFailed(msg)),
+    }
 }
 
 // Update implementations to use the new query function
-async fn query_daku_motoko_tokens(user: &Principal) -> Result<u64, String> {
-    let daku_canister = Principal::from_text(DAKU_MOTOKO_CANISTER)
-        .map_err(|e| format!("Invalid Daku canister ID: {}", e))?;
-
-    match get_tokens_for_user(daku_canister, *user).await {
+async fn query_daku_motoko_tokens(daku_canister: Principal, user: &Principal) -> Result<u64, WalletError> {
+    let result = retry::with_retry(&retry::TOKENS_QUERY_POLICY, "Daku tokens query", || {
+        get_tokens_for_user(daku_canister, *user)
+    }).await;
+    match result {
         Ok(tokens) => Ok(tokens.len() as u64),
         Err((code, msg)) => {
             ic_cdk::print(format!("Daku call error: {:?} - {}", code, msg));
             // Try fallback method
-            query_tokens(DAKU_MOTOKO_CANISTER, user).await
+            query_tokens(&daku_canister.to_text(), user).await
         }
     }
 }
 
-async fn query_gg_album_tokens(user: &Principal) -> Result<u64, String> {
-    let album_canister = Principal::from_text(GG_ALBUM_CANISTER)
-        .map_err(|e| format!("Invalid GG Album canister ID: {}", e))?;
-
-    match get_album_tokens_for_user(album_canister, *user).await {
+async fn query_gg_album_tokens(album_canister: Principal, user: &Principal) -> Result<u64, WalletError> {
+    let result = retry::with_retry(&retry::TOKENS_QUERY_POLICY, "GG Album tokens query", || {
+        get_album_tokens_for_user(album_canister, *user)
+    }).await;
+    match result {
         Ok(tokens) => Ok(tokens.len() as u64),
         Err((code, msg)) => {
             ic_cdk::print(format!("GG Album call error: {:?} - {}", code, msg));
             // Try fallback method
-            query_tokens(GG_ALBUM_CANISTER, user).await
+            query_tokens(&album_canister.to_text(), user).await
         }
     }
 }
 
+// Query a registered collection using the interface it declares
+async fn query_collection_tokens(collection: &Collection, user: &Principal) -> Result<u64, WalletError> {
+    match collection.standard {
+        CollectionStandard::DakuTokens => query_daku_motoko_tokens(collection.canister_id, user).await,
+        CollectionStandard::AlbumTokens => query_gg_album_tokens(collection.canister_id, user).await,
+        CollectionStandard::Ext => query_tokens(&collection.canister_id.to_text(), user).await,
+    }
+}
+
 // Helper to check if we need to refresh cache for a user
 fn should_refresh_cache(user: &Principal) -> bool {
     let current_time = time();
     
     HOLDER_INFO.with(|holder_info| {
-        if let Some(info) = holder_info.borrow().get(user) {
+        if let Some(info) = holder_info.borrow().get(&StablePrincipal(*user)) {
             // Refresh if data is older than CACHE_DURATION
             return current_time - info.last_updated > CACHE_DURATION;
         }
@@ -529,57 +1378,46 @@ fn should_refresh_cache(user: &Principal) -> bool {
 }
 
 // Update holder info for a specific user
-async fn update_holder_info(user: &Principal) -> Result<HolderInfo, String> {
+async fn update_holder_info(user: &Principal) -> Result<HolderInfo, WalletError> {
     ic_cdk::print(format!("Updating holder info for: {}", user));
     
-    // First try primary query methods
-    let daku_result = query_daku_motoko_tokens(user).await;
-    let gg_result = query_gg_album_tokens(user).await;
-    
-    // If primary methods fail, try fallback methods
-    let daku_count = match daku_result {
-        Ok(count) => count,
-        Err(e) => {
-            ic_cdk::print(format!("Primary Daku query failed: {}, trying fallback...", e));
-            // Try fallback query if primary fails
-            match query_tokens(DAKU_MOTOKO_CANISTER, user).await {
-                Ok(count) => count,
-                Err(fallback_err) => {
-                    ic_cdk::print(format!("Fallback Daku query also failed: {}, using 0", fallback_err));
-                    0
-                }
-            }
-        }
-    };
+    let current_time = time();
+    let previous = HOLDER_INFO.with(|holder_info| holder_info.borrow().get(&StablePrincipal(*user)));
+    let mut holdings = Vec::new();
     
-    let gg_count = match gg_result {
-        Ok(count) => count,
-        Err(e) => {
-            ic_cdk::print(format!("Primary GG query failed: {}, trying fallback...", e));
-            // Try fallback query if primary fails
-            match query_tokens(GG_ALBUM_CANISTER, user).await {
-                Ok(count) => count,
-                Err(fallback_err) => {
-                    ic_cdk::print(format!("Fallback GG query also failed: {}, using 0", fallback_err));
-                    0
+    for collection in collections::enabled_collections() {
+        // First try the primary query method for this collection
+        let holding = match query_collection_tokens(&collection, user).await {
+            Ok(count) => CollectionHolding::new(collection.canister_id, count),
+            Err(e) => {
+                ic_cdk::print(format!("Primary {} query failed: {}, trying fallback...", collection.name, e));
+                // Try fallback query if primary fails
+                match query_tokens(&collection.canister_id.to_text(), user).await {
+                    Ok(count) => CollectionHolding::new(collection.canister_id, count),
+                    Err(fallback_err) => {
+                        // Keep the last known count rather than wiping this holder's rewards
+                        ic_cdk::print(format!("Fallback {} query also failed: {}, keeping previous count", collection.name, fallback_err));
+                        let error = fallback_err.to_string();
+                        previous.as_ref()
+                            .and_then(|info| info.stale_holding(&collection.canister_id, &error, current_time))
+                            .unwrap_or_else(|| CollectionHolding {
+                                collection: collection.canister_id,
+                                count: 0,
+                                stale: Some(StaleHolding::new(&error, current_time, None)),
+                            })
+                    }
                 }
             }
-        }
-    };
-    
-    let total_count = daku_count + gg_count;
-    let current_time = time();
+        };
+        
+        holdings.push(holding);
+    }
     
     // Create holder info
-    let info = HolderInfo {
-        daku_count,
-        gg_count,
-        total_count,
-        last_updated: current_time,
-    };
+    let info = HolderInfo::from_holdings(holdings, current_time);
     
-    ic_cdk::print(format!("Final holder info: Daku={}, GG={}, Total={}", 
-                         daku_count, gg_count, total_count));
+    ic_cdk::print(format!("Final holder info: Daku={}, GG={}, Total={}, degraded={}", 
+                         info.daku_count, info.gg_count, info.total_count, info.is_degraded()));
     
     Ok(info)
 }
@@ -591,7 +1429,7 @@ fn get_nft_count(user: Principal) -> NFTProgress {
     
     // Try to get from HOLDER_INFO first
     let holder_info = HOLDER_INFO.with(|holder_info| {
-        holder_info.borrow().get(&user).cloned()
+        holder_info.borrow().get(&StablePrincipal(user))
     });
     
     // If found, convert to NFTProgress
@@ -614,93 +1452,160 @@ fn get_nft_count(user: Principal) -> NFTProgress {
 
 // Helper function that uses pre-configured known holders as fallback
 fn get_holder_info(user: &Principal) -> HolderInfo {
+    let known = KNOWN_HOLDERS.with(|holders_ref| {
+        holders_ref.borrow().get(&StablePrincipal(*user))
+    });
+    
+    // Fall back to the seed list when nothing has been persisted yet
+    known
+        .or_else(|| init_known_holders().remove(user))
+        .unwrap_or_default()
+}
+
+// Seed KNOWN_HOLDERS with the development fallback list if it is still empty
+fn ensure_known_holders() {
     KNOWN_HOLDERS.with(|holders_ref| {
-        // Initialize if empty
         let mut holders = holders_ref.borrow_mut();
         if holders.is_empty() {
-            *holders = init_known_holders();
+            for (principal, info) in init_known_holders() {
+                holders.insert(StablePrincipal(principal), info);
+            }
         }
-        
-        // Return info for this user if known, otherwise default
-        holders.get(user).cloned().unwrap_or_default()
-    })
+    });
+}
+
+// Replace the whole HOLDER_INFO map with a freshly parsed holder set
+fn replace_holder_info(holders: &HashMap<Principal, HolderInfo>) {
+    HOLDER_INFO.with(|holder_info| {
+        let mut holder_info = holder_info.borrow_mut();
+        let stale: Vec<StablePrincipal> = holder_info.iter()
+            .map(|(k, _)| k)
+            .filter(|k| !holders.contains_key(&k.0))
+            .collect();
+        for key in stale {
+            holder_info.remove(&key);
+        }
+        for (principal, info) in holders {
+            let info = HolderInfo { excluded: exclusions::reason_for(principal), ..info.clone() };
+            holder_info.insert(StablePrincipal(*principal), info);
+        }
+    });
 }
 
 #[update]
-fn update_balance(user: Principal, amount: u64) -> u64 {
+fn update_balance(user: Principal, amount: u64) -> Result<u64, WalletError> {
+    require_admin()?;
     BALANCES.with(|balances| {
-        let mut balances = balances.borrow_mut();
-        let current = balances.entry(user).or_insert(0);
-        *current = amount;
-        *current
-    })
+        balances.borrow_mut().insert(StablePrincipal(user), amount);
+    });
+    Ok(amount)
 }
 
 #[query]
 fn get_balance(user: Principal) -> u64 {
     BALANCES.with(|balances| {
-        balances.borrow().get(&user).copied().unwrap_or(0)
+        balances.borrow().get(&StablePrincipal(user)).unwrap_or(0)
     })
 }
 
-// Update NFT count for a specific user
+// Update NFT count for a specific user. Holders refresh themselves through refresh_my_holdings.
+#[update]
+async fn update_nft_count(user: Principal) -> Result<u64, WalletError> {
+    require_admin()?;
+    refresh_holder(user).await.map(|info| info.total_count)
+}
+
+// Re-query the caller's own holdings. Rate limited per caller and canister-wide,
+// since every refresh makes inter-canister calls for each collection.
+#[update]
+async fn refresh_my_holdings() -> Result<HolderInfo, WalletError> {
+    let caller = ic_cdk::caller();
+    if caller == Principal::anonymous() {
+        return Err(WalletError::InvalidArgument("the anonymous principal holds no NFTs".to_string()));
+    }
+    // Checked before charging the caller, since the refresh would be refused anyway
+    if state::get_meta().csv_data_loaded {
+        return Err(WalletError::InvalidArgument("holdings come from an uploaded CSV and change with the next import".to_string()));
+    }
+    refresh_job::ensure_idle()?;
+    self_refresh::acquire(caller, time())?;
+    refresh_holder(caller).await
+}
+
+#[query]
+fn get_self_refresh_config() -> SelfRefreshConfig {
+    self_refresh::config()
+}
+
 #[update]
-async fn update_nft_count(user: Principal) -> u64 {
+fn set_self_refresh_config(config: SelfRefreshConfig) -> Result<SelfRefreshConfig, WalletError> {
+    require_admin()?;
+    self_refresh::set_config(config)
+}
+
+// Re-query one holder and store the result. The snapshot is only bumped when the
+// holdings actually changed, and a principal we don't track that holds nothing isn't
+// stored at all. Refused while the refresh job runs, since its commit rewrites everyone.
+async fn refresh_holder(user: Principal) -> Result<HolderInfo, WalletError> {
+    refresh_job::ensure_idle()?;
     ic_cdk::print(format!("Updating NFT count for: {}", user));
     
     // Set in-progress flag
-    NFT_COUNTS.with(|counts| {
-        let mut counts = counts.borrow_mut();
-        let progress = counts.entry(user).or_insert(NFTProgress::default());
-        progress.in_progress = true;
-    });
+    update_progress(user, |progress| progress.in_progress = true);
     
-    // Try to get updated holder info
-    match update_holder_info(&user).await {
+    // Try to get updated holder info; a job may have started while we were waiting
+    let result = update_holder_info(&user).await;
+    match result.and_then(|info| refresh_job::ensure_idle().map(|_| info)) {
         Ok(info) => {
-            // Update HOLDER_INFO
-            HOLDER_INFO.with(|holder_info| {
-                holder_info.borrow_mut().insert(user, info.clone());
-            });
-            
-            // Update NFT_COUNTS
-            let total_count = info.total_count;
-            NFT_COUNTS.with(|counts| {
-                let mut counts = counts.borrow_mut();
-                let progress = counts.entry(user).or_insert(NFTProgress::default());
-                progress.count = total_count;
-                progress.in_progress = false;
-                progress.last_updated = time();
-            });
+            let current_time = time();
+            let previous = HOLDER_INFO.with(|holder_info| holder_info.borrow().get(&StablePrincipal(user)));
+            match previous {
+                // Nothing worth storing for an untracked principal that holds nothing
+                None if info.total_count == 0 => update_progress(user, |progress| progress.in_progress = false),
+                // Same holdings: note the check without touching the record or the snapshot
+                Some(previous) if previous.holdings() == info.holdings() => {
+                    update_progress(user, |progress| {
+                        progress.in_progress = false;
+                        progress.last_updated = current_time;
+                    });
+                },
+                _ => {
+                    // Update HOLDER_INFO and NFT_COUNTS
+                    put_holder(user, &info, current_time);
+                    state::bump_snapshot(current_time);
+                    archive_holder_changes(&[user]);
+                },
+            }
             
-            total_count
+            Ok(info)
         },
         Err(e) => {
             ic_cdk::print(format!("Error updating NFT count: {}", e));
             
-            // Use fallback data
-            let info = get_holder_info(&user);
-            let total_count = info.total_count;
-            
-            // Update NFT_COUNTS to show error state
-            NFT_COUNTS.with(|counts| {
-                let mut counts = counts.borrow_mut();
-                let progress = counts.entry(user).or_insert(NFTProgress::default());
-                progress.count = total_count;
-                progress.in_progress = false;
-                progress.last_updated = time();
-            });
+            // Clear the in-progress flag but keep the previous count
+            update_progress(user, |progress| progress.in_progress = false);
             
-            total_count
+            Err(e)
         }
     }
 }
 
+// Edit the holder's NFT_COUNTS entry if there is one; untracked principals get none
+fn update_progress<F: FnOnce(&mut NFTProgress)>(user: Principal, f: F) {
+    NFT_COUNTS.with(|counts| {
+        let mut counts = counts.borrow_mut();
+        if let Some(mut progress) = counts.get(&StablePrincipal(user)) {
+            f(&mut progress);
+            counts.insert(StablePrincipal(user), progress);
+        }
+    });
+}
+
 #[query]
 fn get_all_nft_counts() -> Vec<(Principal, NFTProgress)> {
     NFT_COUNTS.with(|counts| {
         counts.borrow().iter()
-            .map(|(k, v)| (*k, v.clone()))
+            .map(|(k, v)| (k.0, v))
             .collect()
     })
 }
@@ -714,8 +1619,11 @@ fn get_debug_info() -> Vec<String> {
     info.push(format!("Wallet Rust Canister v1.2.0"));
     
     // Canister IDs
-    info.push(format!("Daku Canister: {}", DAKU_MOTOKO_CANISTER));
-    info.push(format!("GG Album Canister: {}", GG_ALBUM_CANISTER));
+    for collection in collections::list_collections() {
+        info.push(format!("{} Canister: {} ({:?}, weight {} bps, {})",
+            collection.name, collection.canister_id, collection.standard, collection.reward_weight_bps,
+            if collection.enabled { "enabled" } else { "disabled" }));
+    }
     
     // Cache info
     info.push(format!("Cache duration: {} seconds", CACHE_DURATION / 1_000_000_000));
@@ -731,13 +1639,9 @@ fn get_debug_info() -> Vec<String> {
     info.push(format!("  - user_variant_1: EXT User::Address format"));
     
     // Known holders stats as fallback
-    KNOWN_HOLDERS.with(|holders_ref| {
-        let mut holders = holders_ref.borrow_mut();
-        if holders.is_empty() {
-            *holders = init_known_holders();
-        }
-        info.push(format!("Pre-configured fallback holders: {}", holders.len()));
-    });
+    let known_holders = KNOWN_HOLDERS.with(|holders_ref| holders_ref.borrow().len());
+    let known_holders = if known_holders == 0 { init_known_holders().len() as u64 } else { known_holders };
+    info.push(format!("Pre-configured fallback holders: {}", known_holders));
     
     // Holder stats from HOLDER_INFO (real data)
     HOLDER_INFO.with(|holders| {
@@ -749,7 +1653,7 @@ fn get_debug_info() -> Vec<String> {
         if count > 0 {
             info.push("Sample holder data:".to_string());
             for (i, (principal, data)) in holder_info.iter().enumerate().take(3) {
-                info.push(format!("  Principal {}: {}", i, principal));
+                info.push(format!("  Principal {}: {}", i, principal.0));
                 info.push(format!("    Daku: {}, GG: {}, Total: {}, Updated: {} seconds ago", 
                     data.daku_count, data.gg_count, data.total_count, 
                     (time() - data.last_updated) / 1_000_000_000));
@@ -758,22 +1662,25 @@ fn get_debug_info() -> Vec<String> {
     });
     
     // Last update time
-    LAST_BULK_UPDATE.with(|last_update| {
-        let timestamp = *last_update.borrow();
-        if timestamp > 0 {
-            let seconds_ago = (time() - timestamp) / 1_000_000_000;
-            info.push(format!("Last bulk update: {} seconds ago", seconds_ago));
-        } else {
-            info.push(format!("No bulk update performed yet"));
-        }
-    });
+    let timestamp = state::get_meta().last_bulk_update;
+    if timestamp > 0 {
+        let seconds_ago = (time() - timestamp) / 1_000_000_000;
+        info.push(format!("Last bulk update: {} seconds ago", seconds_ago));
+    } else {
+        info.push(format!("No bulk update performed yet"));
+    }
+    
+    // Stable memory schema
+    info.push(format!("Stable schema version: {}", state::stored_schema_version()));
     
     info
 }
 
-// Test direct canister calls - useful for debugging the integration
+// Test direct canister calls - useful for debugging the integration. Admin only, since
+// it makes calls to every enabled collection.
 #[update]
-async fn test_direct_canister_calls() -> Vec<String> {
+async fn test_direct_canister_calls() -> Result<Vec<String>, WalletError> {
+    require_admin()?;
     let mut debug_logs = Vec::new();
     debug_logs.push("=== Starting direct canister testing ===".to_string());
 
@@ -795,52 +1702,32 @@ async fn test_direct_canister_calls() -> Vec<String> {
     // Test primary query methods
     debug_logs.push("\n=== Testing primary query methods ===".to_string());
     
-    // Query Daku Motoko
-    debug_logs.push("Querying Daku Motoko...".to_string());
-    match query_daku_motoko_tokens(test_user).await {
-        Ok(count) => {
-            debug_logs.push(format!("Daku Motoko success - token count: {}", count));
-        },
-        Err(e) => {
-            debug_logs.push(format!("Daku Motoko error: {}", e));
-        }
-    }
+    let collections = collections::enabled_collections();
     
-    // Query GG Album
-    debug_logs.push("Querying GG Album...".to_string());
-    match query_gg_album_tokens(test_user).await {
-        Ok(count) => {
-            debug_logs.push(format!("GG Album success - token count: {}", count));
-        },
-        Err(e) => {
-            debug_logs.push(format!("GG Album error: {}", e));
+    for collection in collections.iter() {
+        debug_logs.push(format!("Querying {}...", collection.name));
+        match query_collection_tokens(collection, test_user).await {
+            Ok(count) => {
+                debug_logs.push(format!("{} success - token count: {}", collection.name, count));
+            },
+            Err(e) => {
+                debug_logs.push(format!("{} error: {}", collection.name, e));
+            }
         }
     }
     
     // Test fallback query methods
     debug_logs.push("\n=== Testing fallback query methods ===".to_string());
     
-    // Test Daku Motoko fallback
-    let _daku_canister = Principal::from_text(DAKU_MOTOKO_CANISTER).unwrap_or(Principal::anonymous());
-    debug_logs.push("Testing Daku Motoko fallback method...".to_string());
-    match query_tokens(DAKU_MOTOKO_CANISTER, test_user).await {
-        Ok(count) => {
-            debug_logs.push(format!("Daku Motoko fallback success - token count: {}", count));
-        },
-        Err(e) => {
-            debug_logs.push(format!("Daku Motoko fallback error: {}", e));
-        }
-    }
-    
-    // Test GG Album fallback
-    let _gg_canister = Principal::from_text(GG_ALBUM_CANISTER).unwrap_or(Principal::anonymous());
-    debug_logs.push("Testing GG Album fallback method...".to_string());
-    match query_tokens(GG_ALBUM_CANISTER, test_user).await {
-        Ok(count) => {
-            debug_logs.push(format!("GG Album fallback success - token count: {}", count));
-        },
-        Err(e) => {
-            debug_logs.push(format!("GG Album fallback error: {}", e));
+    for collection in collections.iter() {
+        debug_logs.push(format!("Testing {} fallback method...", collection.name));
+        match query_tokens(&collection.canister_id.to_text(), test_user).await {
+            Ok(count) => {
+                debug_logs.push(format!("{} fallback success - token count: {}", collection.name, count));
+            },
+            Err(e) => {
+                debug_logs.push(format!("{} fallback error: {}", collection.name, e));
+            }
         }
     }
     
@@ -857,22 +1744,18 @@ async fn test_direct_canister_calls() -> Vec<String> {
     }
     
     debug_logs.push("\n=== Test completed ===".to_string());
-    debug_logs
+    Ok(debug_logs)
 }
 
+// Query one canister for a principal's tokens; admin only, like test_direct_canister_calls
 #[update]
-async fn test_ext_query(canister_id: String, principal_id: String) -> Vec<String> {
+async fn test_ext_query(canister_id: String, principal_id: String) -> Result<Vec<String>, WalletError> {
+    require_admin()?;
     let mut logs = Vec::new();
     logs.push(format!("Testing EXT query for canister {} with principal {}", canister_id, principal_id));
     
-    // Parse the principal or use anonymous
-    let principal = match Principal::from_text(&principal_id) {
-        Ok(p) => p,
-        Err(_) => {
-            logs.push(format!("Invalid principal ID, using anonymous"));
-            Principal::anonymous()
-        }
-    };
+    let principal = Principal::from_text(&principal_id)
+        .map_err(|_| WalletError::InvalidPrincipal(principal_id.clone()))?;
     
     // Try to query tokens
     match query_tokens(&canister_id, &principal).await {
@@ -884,52 +1767,48 @@ async fn test_ext_query(canister_id: String, principal_id: String) -> Vec<String
         }
     }
     
-    logs
+    Ok(logs)
 }
 
 // Add an admin function to set NFT counts directly (for verified wallets)
 #[update]
-fn set_verified_nft_counts(user: Principal, daku_count: u64, gg_count: u64) -> HolderInfo {
+fn set_verified_nft_counts(user: Principal, daku_count: u64, gg_count: u64) -> Result<HolderInfo, WalletError> {
+    require_admin()?;
+    // A running job's commit would overwrite the counts set here
+    refresh_job::ensure_idle()?;
     let current_time = time();
-    let info = HolderInfo {
-        daku_count,
-        gg_count,
-        total_count: daku_count + gg_count,
-        last_updated: current_time,
-    };
-    
-    // Update in holder info
-    HOLDER_INFO.with(|holder_info| {
-        holder_info.borrow_mut().insert(user, info.clone());
-    });
+    let info = HolderInfo::from_holdings(vec![
+        CollectionHolding::new(collections::daku_canister(), daku_count),
+        CollectionHolding::new(collections::gg_canister(), gg_count),
+    ], current_time);
     
-    // Also update NFT_COUNTS for compatibility
-    NFT_COUNTS.with(|counts| {
-        counts.borrow_mut().insert(user, NFTProgress {
-            count: info.total_count,
-            in_progress: false,
-            last_updated: current_time,
-        });
-    });
+    // Update in holder info, and NFT_COUNTS for compatibility
+    put_holder(user, &info, current_time);
+    state::bump_snapshot(current_time);
+    archive_holder_changes(&[user]);
     
     // Also update in known holders for future fallback
     KNOWN_HOLDERS.with(|holders| {
-        holders.borrow_mut().insert(user, info.clone());
+        holders.borrow_mut().insert(StablePrincipal(user), info.clone());
     });
     
-    info
+    account_index::index_principal(&user);
+    
+    Ok(info)
 }
 
 // Optimization: Bulk update method that uses less cycles
 #[update]
-async fn bulk_update_nft_counts(users: Vec<Principal>) -> Vec<(Principal, u64)> {
+async fn bulk_update_nft_counts(users: Vec<Principal>) -> Result<Vec<(Principal, u64)>, WalletError> {
+    require_admin()?;
+    refresh_job::ensure_idle()?;
     let mut results = Vec::new();
     let current_time = time();
     
     for user in users.iter() {
         // Check cache first to avoid unnecessary queries
         let should_update = NFT_COUNTS.with(|counts| {
-            if let Some(progress) = counts.borrow().get(user) {
+            if let Some(progress) = counts.borrow().get(&StablePrincipal(*user)) {
                 // Only update if cache is expired
                 current_time - progress.last_updated > CACHE_DURATION
             } else {
@@ -938,28 +1817,21 @@ async fn bulk_update_nft_counts(users: Vec<Principal>) -> Vec<(Principal, u64)>
         });
         
         if should_update {
-            // Only make expensive canister calls if necessary
-            match update_holder_info(user).await {
+            // Only make expensive canister calls if necessary. A job started while we were
+            // waiting owns HOLDER_INFO, so the holder then gets its cached count, as on error.
+            let result = update_holder_info(user).await;
+            match result.and_then(|info| refresh_job::ensure_idle().map(|_| info)) {
                 Ok(info) => {
-                    HOLDER_INFO.with(|holder_info| {
-                        holder_info.borrow_mut().insert(*user, info.clone());
-                    });
-                    
-                    // Also update NFT_COUNTS for compatibility
-                    NFT_COUNTS.with(|counts| {
-                        counts.borrow_mut().insert(*user, NFTProgress {
-                            count: info.total_count,
-                            in_progress: false,
-                            last_updated: current_time,
-                        });
-                    });
+                    put_holder(*user, &info, current_time);
+                    state::bump_snapshot(current_time);
+                    archive_holder_changes(&[*user]);
                     
                     results.push((*user, info.total_count));
                 },
                 Err(_) => {
                     // Fallback to cached value or 0
                     let count = NFT_COUNTS.with(|counts| {
-                        counts.borrow().get(user).map_or(0, |p| p.count)
+                        counts.borrow().get(&StablePrincipal(*user)).map_or(0, |p| p.count)
                     });
                     results.push((*user, count));
                 }
@@ -967,52 +1839,23 @@ async fn bulk_update_nft_counts(users: Vec<Principal>) -> Vec<(Principal, u64)>
         } else {
             // Use cached value
             let count = NFT_COUNTS.with(|counts| {
-                counts.borrow().get(user).map_or(0, |p| p.count)
+                counts.borrow().get(&StablePrincipal(*user)).map_or(0, |p| p.count)
             });
             results.push((*user, count));
         }
     }
     
-    LAST_BULK_UPDATE.with(|last_update| {
-        *last_update.borrow_mut() = current_time;
-    });
+    state::update_meta(|meta| meta.last_bulk_update = current_time);
     
-    results
+    Ok(results)
 }
 
-// Optimization: Add exponential backoff retry helper for more efficient retries
-async fn retry_with_backoff<T, F, Fut>(operation: F, max_retries: u8) -> Result<T, String> 
-where
-    F: Fn() -> Fut,
-    Fut: std::future::Future<Output = Result<T, String>>,
-{
-    let mut retries = 0;
-    let mut delay_ms = 100; // Start with 100ms delay
-    
-    loop {
-        match operation().await {
-            Ok(result) => return Ok(result),
-            Err(e) => {
-                retries += 1;
-                if retries >= max_retries {
-                    return Err(format!("Operation failed after {} retries: {}", max_retries, e));
-                }
-                
-                // Sleep with exponential backoff
-                ic_cdk::print(format!("Retry {} failed, waiting {}ms: {}", retries, delay_ms, e));
-                
-                // Simple delay using async
-                let start = time();
-                let delay_nanos = delay_ms as u64 * 1_000_000; // Convert ms to ns
-                while time() < start + delay_nanos {
-                    // Yield to allow other work
-                    async {}.await;
-                }
-                
-                // Exponential backoff with max of 5 seconds
-                delay_ms = std::cmp::min(delay_ms * 2, 5000);
-            }
-        }
+// No-op used by retry::with_retry to yield between attempts. Only the canister itself
+// may call it, so it is left out of the public interface.
+#[update]
+fn retry_backoff_tick() {
+    if ic_cdk::caller() != ic_cdk::id() {
+        ic_cdk::trap("retry_backoff_tick can only be called by this canister");
     }
 }
 
@@ -1021,62 +1864,66 @@ pub struct GetAllTokensResponse {
     pub total_count: u64,
     pub daku_count: u64,
     pub gg_album_count: u64,
+    pub collections: Vec<CollectionHolding>,
     pub errors: Vec<String>,
 }
 
+// Per-collection failures are reported in `errors` next to the counts that did succeed.
+// Admin only: every call queries each collection canister.
 #[ic_cdk::update]
-async fn get_all_tokens(user: String) -> GetAllTokensResponse {
+async fn get_all_tokens(user: String) -> Result<GetAllTokensResponse, WalletError> {
+    require_admin()?;
+    let principal = Principal::from_text(&user).map_err(|_| WalletError::InvalidPrincipal(user.clone()))?;
     let mut response = GetAllTokensResponse::default();
     
-    match Principal::from_text(&user) {
-        Ok(principal) => {
-            // Query Daku tokens
-            match query_daku_motoko_tokens(&principal).await {
-                Ok(count) => {
+    let daku_canister = collections::daku_canister();
+    let gg_canister = collections::gg_canister();
+    
+    for collection in collections::enabled_collections() {
+        match query_collection_tokens(&collection, &principal).await {
+            Ok(count) => {
+                if collection.canister_id == daku_canister {
                     response.daku_count = count;
-                    response.total_count += count;
-                }
-                Err(e) => {
-                    response.errors.push(format!("Failed to query Daku tokens: {}", e));
-                }
-            }
-            
-            // Query GG Album tokens
-            match query_gg_album_tokens(&principal).await {
-                Ok(count) => {
+                } else if collection.canister_id == gg_canister {
                     response.gg_album_count = count;
-                    response.total_count += count;
-                }
-                Err(e) => {
-                    response.errors.push(format!("Failed to query GG Album tokens: {}", e));
                 }
+                response.total_count += count;
+                response.collections.push(CollectionHolding::new(collection.canister_id, count));
+            }
+            Err(e) => {
+                response.errors.push(format!("Failed to query {} tokens: {}", collection.name, e));
             }
-        },
-        Err(e) => {
-            response.errors.push(format!("Invalid principal: {}", e));
         }
     }
     
-    response
+    Ok(response)
 }
 
-// Updated registry query function
+// Fetch a collection's registry as text; admin only, since it calls out to the canister
 #[ic_cdk::update]
-async fn get_nft_registry(canister_id: String) -> String {
+async fn get_nft_registry(canister_id: String) -> Result<String, WalletError> {
+    require_admin()?;
     let mut result = String::new();
+    // Code of the last rejected call, reported if every method fails
+    let mut last_rejection: Option<RejectionCode> = None;
     
     // Validate canister ID format
     match Principal::from_text(&canister_id) {
         Ok(canister_principal) => {
             // Validate that this looks like a canister ID (not a user principal)
             if canister_principal.as_slice().len() < 10 {
-                return format!("Error: {} doesn't appear to be a valid canister ID (too short)", canister_id);
+                return Err(WalletError::InvalidPrincipal(canister_id));
             }
             
             let mut success = false;
             
+            // Collections registered with the album interface use the GG registry format
+            let is_album = collections::get_collection(&canister_principal)
+                .map(|c| c.standard == CollectionStandard::AlbumTokens)
+                .unwrap_or(canister_id == GG_ALBUM_CANISTER);
+            
             // Check if this is GG Album canister
-            if canister_id == GG_ALBUM_CANISTER {
+            if is_album {
                 match get_gg_registry_records(canister_principal).await {
                     Ok(records) => {
                         success = true;
@@ -1101,6 +1948,7 @@ async fn get_nft_registry(canister_id: String) -> String {
                         }
                     },
                     Err((code, msg)) => {
+                        last_rejection = Some(code);
                         result.push_str(&format!("Error querying GG Album registry format: {:?} - {}\n", code, msg));
                     }
                 }
@@ -1132,6 +1980,7 @@ async fn get_nft_registry(canister_id: String) -> String {
                         }
                     },
                     Err((code, msg)) => {
+                        last_rejection = Some(code);
                         result.push_str(&format!("Error querying Daku registry format: {:?} - {}\n", code, msg));
                     }
                 }
@@ -1140,7 +1989,7 @@ async fn get_nft_registry(canister_id: String) -> String {
             // Try raw string method as fallback
             if !success {
                 // Try GG Album raw first if that's the target canister
-                if canister_id == GG_ALBUM_CANISTER {
+                if is_album {
                     match get_gg_registry_raw(canister_principal).await {
                         Ok(registry) => {
                             success = true;
@@ -1153,7 +2002,8 @@ async fn get_nft_registry(canister_id: String) -> String {
                                 result.push_str(&format!("Registry for {} (raw):\n{:?}", canister_id, registry));
                             }
                         },
-                        Err((_, error)) => {
+                        Err((code, error)) => {
+                            last_rejection = Some(code);
                             result.push_str(&format!("Failed to get raw GG Album registry: {}\n", error));
                         }
                     }
@@ -1172,7 +2022,8 @@ async fn get_nft_registry(canister_id: String) -> String {
                                 result.push_str(&format!("Registry for {} (raw):\n{:?}", canister_id, registry));
                             }
                         },
-                        Err((_, error)) => {
+                        Err((code, error)) => {
+                            last_rejection = Some(code);
                             result.push_str(&format!("Failed to get raw registry: {}\n", error));
                         }
                     }
@@ -1181,7 +2032,7 @@ async fn get_nft_registry(canister_id: String) -> String {
             
             // Try alternative method (token vector)
             if !success {
-                if canister_id == GG_ALBUM_CANISTER {
+                if is_album {
                     match get_gg_registry_tokens(canister_principal).await {
                         Ok(tokens) => {
                             success = true;
@@ -1199,6 +2050,7 @@ async fn get_nft_registry(canister_id: String) -> String {
                             }
                         },
                         Err((inner_code, inner_msg)) => {
+                            last_rejection = Some(inner_code);
                             result.push_str(&format!("Error querying GG token registry: {:?} - {}\n", inner_code, inner_msg));
                         }
                     }
@@ -1220,6 +2072,7 @@ async fn get_nft_registry(canister_id: String) -> String {
                             }
                         },
                         Err((inner_code, inner_msg)) => {
+                            last_rejection = Some(inner_code);
                             result.push_str(&format!("Error querying token registry: {:?} - {}\n", inner_code, inner_msg));
                         }
                     }
@@ -1228,11 +2081,11 @@ async fn get_nft_registry(canister_id: String) -> String {
             
             // Try getting registry as a HashMap
             if !success {
-                if canister_id == GG_ALBUM_CANISTER {
+                if is_album {
                     match get_gg_registry_map(canister_principal).await {
-                        Ok(map) => {
+                        Ok((map, unresolved)) => {
                             success = true;
-                            result.push_str(&format!("Registry for {} (GG map): {} entries\n", canister_id, map.len()));
+                            result.push_str(&format!("Registry for {} (GG map): {} entries, {} unresolved account ids\n", canister_id, map.len(), unresolved));
                             
                             // Limit to first 20 entries to avoid excessive output
                             let mut count = 0;
@@ -1246,14 +2099,15 @@ async fn get_nft_registry(canister_id: String) -> String {
                             }
                         },
                         Err((map_code, map_msg)) => {
+                            last_rejection = Some(map_code);
                             result.push_str(&format!("Error querying GG registry map: {:?} - {}\n", map_code, map_msg));
                         }
                     }
                 } else {
                     match get_registry_map(canister_principal).await {
-                        Ok(map) => {
+                        Ok((map, unresolved)) => {
                             success = true;
-                            result.push_str(&format!("Registry for {} (map): {} entries\n", canister_id, map.len()));
+                            result.push_str(&format!("Registry for {} (map): {} entries, {} unresolved account ids\n", canister_id, map.len(), unresolved));
                             
                             // Limit to first 20 entries to avoid excessive output
                             let mut count = 0;
@@ -1267,6 +2121,7 @@ async fn get_nft_registry(canister_id: String) -> String {
                             }
                         },
                         Err((map_code, map_msg)) => {
+                            last_rejection = Some(map_code);
                             result.push_str(&format!("Error querying registry map: {:?} - {}\n", map_code, map_msg));
                         }
                     }
@@ -1276,9 +2131,9 @@ async fn get_nft_registry(canister_id: String) -> String {
             // Try getting registry entries as records
             if !success {
                 match get_registry_entries(canister_principal).await {
-                    Ok(entries) => {
+                    Ok((entries, unresolved)) => {
                         success = true;
-                        result.push_str(&format!("Found {} registry entries\n", entries.len()));
+                        result.push_str(&format!("Found {} registry entries ({} unresolved account ids)\n", entries.len(), unresolved));
                         if entries.len() > 0 {
                             for entry in entries.iter().take(10) {
                                 result.push_str(&format!("Token: {}, Owner: {}\n", entry.0, entry.1.to_text()));
@@ -1289,7 +2144,8 @@ async fn get_nft_registry(canister_id: String) -> String {
                         }
                         result.push_str(&format!("Registry for {} (entries):\n{} entries\n", canister_id, entries.len()));
                     },
-                    Err((_, error)) => {
+                    Err((code, error)) => {
+                        last_rejection = Some(code);
                         result.push_str(&format!("Failed to get registry entries: {}\n", error));
                     }
                 }
@@ -1300,18 +2156,27 @@ async fn get_nft_registry(canister_id: String) -> String {
                 result.push_str("\nAttempting to use EXT standard query...");
                 match query_tokens(&canister_id, &Principal::anonymous()).await {
                     Ok(count) => {
+                        success = true;
                         result.push_str(&format!("\nEXT query successful: {} tokens found", count));
                     },
                     Err(err) => {
+                        if let WalletError::CanisterUnreachable { code, .. } = err {
+                            last_rejection = Some(code);
+                        }
                         result.push_str(&format!("\nEXT query failed: {}", err));
                     }
                 }
             }
+            
+            if !success {
+                return Err(match last_rejection {
+                    Some(code) => WalletError::CanisterUnreachable { code, msg: result },
+                    None => WalletError::DecodeFailed(result),
+                });
+            }
         },
-        Err(e) => {
-            result.push_str(&format!("Invalid canister ID format: {}. Make sure you're using a correct canister ID.", e));
-        }
+        Err(_) => return Err(WalletError::InvalidPrincipal(canister_id)),
     }
     
-    result
+    Ok(result)
 }
\ No newline at end of file
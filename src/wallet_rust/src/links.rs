@@ -0,0 +1,234 @@
+use candid::{CandidType, Principal};
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+
+use crate::errors::WalletError;
+use crate::state::{
+    self, read_principal, write_principal, Memory, StablePrincipal, LINK_PROPOSALS_MEMORY_ID, PRINCIPAL_SLOT,
+    WALLET_LINKS_MEMORY_ID,
+};
+
+// Wallets linked under one primary, the primary not counted
+const MAX_LINKED_WALLETS: usize = 10;
+// Open proposals a principal may have at a time
+const MAX_OPEN_PROPOSALS: usize = 5;
+const PROPOSAL_TTL_NANOS: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+
+// A linked wallet whose holdings count towards `primary`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct WalletLink {
+    pub primary: Principal,
+    pub linked_at: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct LinkedWallet {
+    pub principal: Principal,
+    pub linked_at: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct LinkProposal {
+    pub proposer: Principal,
+    pub target: Principal,
+    pub proposed_at: u64,
+    pub expires_at: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct LinkedWallets {
+    // Principal the group's rewards are computed and paid for
+    pub primary: Principal,
+    pub linked: Vec<LinkedWallet>,
+    // Unexpired proposals made by or to the principal that was asked about
+    pub pending: Vec<LinkProposal>,
+}
+
+state::impl_candid_storable!(WalletLink, 96);
+
+// (proposer, target)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct ProposalKey {
+    proposer: Principal,
+    target: Principal,
+}
+
+impl Storable for ProposalKey {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = Vec::with_capacity(Self::MAX_SIZE as usize);
+        write_principal(&mut bytes, &self.proposer);
+        write_principal(&mut bytes, &self.target);
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        ProposalKey {
+            proposer: read_principal(&bytes),
+            target: read_principal(&bytes[PRINCIPAL_SLOT..]),
+        }
+    }
+}
+
+impl BoundedStorable for ProposalKey {
+    const MAX_SIZE: u32 = 2 * PRINCIPAL_SLOT as u32;
+    const IS_FIXED_SIZE: bool = true;
+}
+
+thread_local! {
+    // Linked wallet -> its primary. Primaries themselves have no entry.
+    static LINKS: RefCell<StableBTreeMap<StablePrincipal, WalletLink, Memory>> =
+        RefCell::new(StableBTreeMap::init(state::memory(WALLET_LINKS_MEMORY_ID)));
+
+    // Proposal -> time it was made
+    static PROPOSALS: RefCell<StableBTreeMap<ProposalKey, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(state::memory(LINK_PROPOSALS_MEMORY_ID)));
+}
+
+fn link_of(principal: &Principal) -> Option<WalletLink> {
+    LINKS.with(|links| links.borrow().get(&StablePrincipal(*principal)))
+}
+
+// The principal whose rewards include `principal`'s holdings; itself if it isn't linked
+pub fn primary_of(principal: &Principal) -> Principal {
+    link_of(principal).map_or(*principal, |link| link.primary)
+}
+
+fn linked_to(primary: &Principal) -> Vec<LinkedWallet> {
+    LINKS.with(|links| {
+        links.borrow().iter()
+            .filter(|(_, link)| link.primary == *primary)
+            .map(|(member, link)| LinkedWallet { principal: member.0, linked_at: link.linked_at })
+            .collect()
+    })
+}
+
+fn proposal(key: ProposalKey, proposed_at: u64) -> LinkProposal {
+    LinkProposal {
+        proposer: key.proposer,
+        target: key.target,
+        proposed_at,
+        expires_at: proposed_at.saturating_add(PROPOSAL_TTL_NANOS),
+    }
+}
+
+fn prune_expired(now: u64) {
+    PROPOSALS.with(|proposals| {
+        let mut proposals = proposals.borrow_mut();
+        let expired: Vec<ProposalKey> = proposals.iter()
+            .filter(|(_, proposed_at)| proposed_at.saturating_add(PROPOSAL_TTL_NANOS) <= now)
+            .map(|(key, _)| key)
+            .collect();
+        for key in expired {
+            proposals.remove(&key);
+        }
+    });
+}
+
+// Proposals made by `proposer`; expired ones should be pruned first
+fn open_proposals_of(proposer: &Principal) -> usize {
+    let start = ProposalKey { proposer: *proposer, target: Principal::from_slice(&[]) };
+    PROPOSALS.with(|proposals| {
+        proposals.borrow().range(start..)
+            .take_while(|(key, _)| key.proposer == *proposer)
+            .count()
+    })
+}
+
+// Drop every proposal `proposer` has open. Called when its primary changes: the target
+// agreed to join the group the proposer was in, not whichever one it is in later.
+fn cancel_proposals_by(proposer: &Principal) {
+    let start = ProposalKey { proposer: *proposer, target: Principal::from_slice(&[]) };
+    PROPOSALS.with(|proposals| {
+        let mut proposals = proposals.borrow_mut();
+        let keys: Vec<ProposalKey> = proposals.range(start..)
+            .take_while(|(key, _)| key.proposer == *proposer)
+            .map(|(key, _)| key)
+            .collect();
+        for key in keys {
+            proposals.remove(&key);
+        }
+    });
+}
+
+// `proposer` asks to add `target` to its group. Nothing is linked until `target` confirms.
+pub fn propose(proposer: Principal, target: Principal, now: u64) -> Result<LinkProposal, WalletError> {
+    if target == proposer {
+        return Err(WalletError::InvalidArgument("a wallet cannot be linked to itself".to_string()));
+    }
+    if target == Principal::anonymous() {
+        return Err(WalletError::InvalidArgument("the anonymous principal cannot be linked".to_string()));
+    }
+    if primary_of(&target) == primary_of(&proposer) {
+        return Err(WalletError::InvalidArgument(format!("{} is already linked to this wallet", target)));
+    }
+    prune_expired(now);
+    let key = ProposalKey { proposer, target };
+    let exists = PROPOSALS.with(|proposals| proposals.borrow().contains_key(&key));
+    if !exists && open_proposals_of(&proposer) >= MAX_OPEN_PROPOSALS {
+        return Err(WalletError::InvalidArgument(format!(
+            "at most {} link proposals can be open at a time", MAX_OPEN_PROPOSALS
+        )));
+    }
+    // Proposing again restarts the expiry
+    PROPOSALS.with(|proposals| proposals.borrow_mut().insert(key, now));
+    Ok(proposal(key, now))
+}
+
+pub fn cancel(proposer: Principal, target: Principal) -> bool {
+    PROPOSALS.with(|proposals| proposals.borrow_mut().remove(&ProposalKey { proposer, target }).is_some())
+}
+
+// `target` accepts `proposer`'s proposal and joins the proposer's group. The target must
+// not be linked yet, which keeps every group one level deep.
+pub fn confirm(target: Principal, proposer: Principal, now: u64) -> Result<LinkedWallets, WalletError> {
+    prune_expired(now);
+    let key = ProposalKey { proposer, target };
+    if !PROPOSALS.with(|proposals| proposals.borrow().contains_key(&key)) {
+        return Err(WalletError::InvalidArgument(format!("no open link proposal from {}", proposer)));
+    }
+    if link_of(&target).is_some() || !linked_to(&target).is_empty() {
+        return Err(WalletError::InvalidArgument("unlink this wallet from its current group first".to_string()));
+    }
+    let primary = primary_of(&proposer);
+    if linked_to(&primary).len() >= MAX_LINKED_WALLETS {
+        return Err(WalletError::InvalidArgument(format!(
+            "a primary wallet can have at most {} linked wallets", MAX_LINKED_WALLETS
+        )));
+    }
+
+    PROPOSALS.with(|proposals| proposals.borrow_mut().remove(&key));
+    cancel_proposals_by(&target);
+    LINKS.with(|links| {
+        links.borrow_mut().insert(StablePrincipal(target), WalletLink { primary, linked_at: now });
+    });
+    ic_cdk::print(format!("Wallet {} linked to primary {}", target, primary));
+    Ok(linked_wallets(&target, now))
+}
+
+// Split `member` off its group. Either the member or its primary may do this.
+pub fn unlink(caller: Principal, member: Principal) -> Result<bool, WalletError> {
+    let Some(link) = link_of(&member) else {
+        return Ok(false);
+    };
+    if caller != member && caller != link.primary {
+        return Err(WalletError::InvalidArgument(format!("{} is not linked to the caller", member)));
+    }
+    LINKS.with(|links| links.borrow_mut().remove(&StablePrincipal(member)));
+    cancel_proposals_by(&member);
+    ic_cdk::print(format!("Wallet {} unlinked from primary {} by {}", member, link.primary, caller));
+    Ok(true)
+}
+
+pub fn linked_wallets(principal: &Principal, now: u64) -> LinkedWallets {
+    let primary = primary_of(principal);
+    let pending = PROPOSALS.with(|proposals| {
+        proposals.borrow().iter()
+            .filter(|(key, _)| key.proposer == *principal || key.target == *principal)
+            .map(|(key, proposed_at)| proposal(key, proposed_at))
+            .filter(|proposal| proposal.expires_at > now)
+            .collect()
+    });
+    LinkedWallets { primary, linked: linked_to(&primary), pending }
+}
@@ -3,4 +3,23 @@ pub mod daku_interface;
 pub mod gg_album_interface;
 pub mod nft_registry_interface;
 pub mod gg_registry_interface;
-pub mod csv_loader; 
\ No newline at end of file
+pub mod csv_loader; 
+pub mod state;
+pub mod errors;
+pub mod access;
+pub mod collections;
+pub mod account_index;
+pub mod refresh;
+pub mod scheduler;
+pub mod retry;
+pub mod refresh_job;
+pub mod csv_reader;
+pub mod csv_import;
+pub mod tokens;
+pub mod data_quality;
+pub mod history;
+pub mod rewards;
+pub mod exclusions;
+pub mod recipients;
+pub mod links;
+pub mod self_refresh;
\ No newline at end of file
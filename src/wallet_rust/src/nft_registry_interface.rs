@@ -2,6 +2,8 @@ use candid::{CandidType, Nat, Principal, IDLValue};
 use ic_cdk::api::call::RejectionCode;
 use std::collections::HashMap;
 
+use crate::account_index::resolve_registry;
+
 // Define registry entry type - more flexible for different canister implementations
 #[derive(CandidType, candid::Deserialize, Debug)]
 pub struct RegistryValue {
@@ -112,34 +114,16 @@ pub async fn get_registry_tokens(
 // Try a different approach: Query registry as HashMap<TokenIndex, Owner>
 pub async fn get_registry_map(
     canister_id: Principal,
-) -> Result<HashMap<TokenIndex, Principal>, (RejectionCode, String)> {
-    // Call getRegistry with the correct interface
-    match ic_cdk::api::call::call::<(), (Vec<(TokenIndex, AccountId)>,)>(
-        canister_id,
-        "getRegistry",
-        ()
-    ).await {
-        Ok((records,)) => {
-            // Convert to HashMap but note that we're interpreting the string as a principal
-            // which may not be accurate - this is a best-effort conversion
-            let mut map = HashMap::new();
-            for (index, owner_id) in records {
-                // Try to convert the owner_id to a principal
-                // If this fails, we'll just skip this entry
-                if let Ok(principal) = Principal::from_text(&owner_id) {
-                    map.insert(index, principal);
-                }
-            }
-            Ok(map)
-        },
-        Err(err) => Err(err)
-    }
+) -> Result<(HashMap<TokenIndex, Principal>, u64), (RejectionCode, String)> {
+    // Owners are resolved through the account-id index; the second value counts owners we couldn't map
+    let (entries, unresolved) = get_registry_entries(canister_id).await?;
+    Ok((entries.into_iter().collect(), unresolved))
 }
 
 // Get registry entries as (token_id, principal) tuples
 pub async fn get_registry_entries(
     canister_id: Principal,
-) -> Result<Vec<(TokenIndex, Principal)>, (RejectionCode, String)> {
+) -> Result<(Vec<(TokenIndex, Principal)>, u64), (RejectionCode, String)> {
     // Call getRegistry with the correct interface
     match ic_cdk::api::call::call::<(), (Vec<(TokenIndex, AccountId)>,)>(
         canister_id,
@@ -147,17 +131,9 @@ pub async fn get_registry_entries(
         ()
     ).await {
         Ok((records,)) => {
-            // Convert to (TokenIndex, Principal) tuples, skipping any entries where
-            // the owner_id can't be parsed as a Principal
-            let entries: Vec<(TokenIndex, Principal)> = records.into_iter()
-                .filter_map(|(index, owner_id)| {
-                    match Principal::from_text(&owner_id) {
-                        Ok(principal) => Some((index, principal)),
-                        Err(_) => None
-                    }
-                })
-                .collect();
-            Ok(entries)
+            // EXT registries return hex AccountIdentifiers, so map them back to
+            // principals through the account-id index instead of parsing them as text
+            Ok(resolve_registry(records))
         },
         Err(err) => Err(err)
     }
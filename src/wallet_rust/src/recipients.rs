@@ -0,0 +1,153 @@
+use candid::{CandidType, Principal};
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+
+use crate::errors::WalletError;
+use crate::state::{
+    self, read_principal, write_principal, Memory, StablePrincipal, PRINCIPAL_SLOT, RECIPIENTS_MEMORY_ID,
+    RECIPIENT_HISTORY_MEMORY_ID,
+};
+
+const SUBACCOUNT_LEN: usize = 32;
+
+// Changes kept per holder; older ones are dropped first
+const MAX_HISTORY_PER_HOLDER: usize = 32;
+
+// ICRC-1 account; no subaccount means the owner's default account
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Account {
+    pub owner: Principal,
+    pub subaccount: Option<Vec<u8>>,
+}
+
+impl Account {
+    pub fn of(owner: Principal) -> Self {
+        Account { owner, subaccount: None }
+    }
+
+    // The all-zero subaccount is the default account, so it is stored and compared as None
+    pub fn normalized(self) -> Self {
+        let subaccount = self.subaccount.filter(|subaccount| subaccount.iter().any(|byte| *byte != 0));
+        Account { subaccount, ..self }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RecipientChange {
+    pub changed_at: u64,
+    // None when the holder went back to being paid directly
+    pub recipient: Option<Account>,
+}
+
+state::impl_candid_storable!(Account, 128);
+state::impl_candid_storable!(RecipientChange, 160);
+
+// (holder, sequence number), so a holder's changes sit together in order
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct ChangeKey {
+    holder: Principal,
+    seq: u64,
+}
+
+impl Storable for ChangeKey {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = Vec::with_capacity(Self::MAX_SIZE as usize);
+        write_principal(&mut bytes, &self.holder);
+        bytes.extend_from_slice(&self.seq.to_be_bytes());
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        ChangeKey {
+            holder: read_principal(&bytes),
+            seq: u64::from_be_bytes(bytes[PRINCIPAL_SLOT..].try_into().expect("sequence number is 8 bytes")),
+        }
+    }
+}
+
+impl BoundedStorable for ChangeKey {
+    const MAX_SIZE: u32 = PRINCIPAL_SLOT as u32 + 8;
+    const IS_FIXED_SIZE: bool = true;
+}
+
+thread_local! {
+    // Holder -> account their rewards are paid to, for holders who delegated
+    static RECIPIENTS: RefCell<StableBTreeMap<StablePrincipal, Account, Memory>> =
+        RefCell::new(StableBTreeMap::init(state::memory(RECIPIENTS_MEMORY_ID)));
+
+    static HISTORY: RefCell<StableBTreeMap<ChangeKey, RecipientChange, Memory>> =
+        RefCell::new(StableBTreeMap::init(state::memory(RECIPIENT_HISTORY_MEMORY_ID)));
+}
+
+// Where rewards for `holder` go: the delegated account, or the holder's default account
+pub fn resolve(holder: &Principal) -> Account {
+    RECIPIENTS.with(|recipients| recipients.borrow().get(&StablePrincipal(*holder)))
+        .map_or_else(|| Account::of(*holder), Account::normalized)
+}
+
+pub fn validate(account: &Account) -> Result<(), WalletError> {
+    if account.owner == Principal::anonymous() {
+        return Err(WalletError::InvalidArgument("recipient owner cannot be the anonymous principal".to_string()));
+    }
+    if account.subaccount.as_ref().is_some_and(|subaccount| subaccount.len() != SUBACCOUNT_LEN) {
+        return Err(WalletError::InvalidArgument(format!("subaccount must be {} bytes", SUBACCOUNT_LEN)));
+    }
+    Ok(())
+}
+
+// Point `holder`'s rewards at `recipient`, or back at the holder for None.
+// Returns false, logging nothing, when the effective recipient is unchanged.
+pub fn set(holder: Principal, recipient: Option<Account>, changed_at: u64) -> Result<bool, WalletError> {
+    if let Some(account) = &recipient {
+        validate(account)?;
+    }
+    // Delegating to one's own default account is the same as not delegating
+    let recipient = recipient.map(Account::normalized).filter(|account| *account != Account::of(holder));
+    // Records from before normalization may still hold an all-zero subaccount
+    let previous = RECIPIENTS.with(|recipients| recipients.borrow().get(&StablePrincipal(holder)))
+        .map(Account::normalized);
+    if previous == recipient {
+        return Ok(false);
+    }
+
+    RECIPIENTS.with(|recipients| {
+        let mut recipients = recipients.borrow_mut();
+        match &recipient {
+            Some(account) => recipients.insert(StablePrincipal(holder), account.clone()),
+            None => recipients.remove(&StablePrincipal(holder)),
+        };
+    });
+    log_change(holder, RecipientChange { changed_at, recipient });
+    Ok(true)
+}
+
+fn log_change(holder: Principal, change: RecipientChange) {
+    ic_cdk::print(format!("Reward recipient of {} set to {}", holder, match &change.recipient {
+        Some(account) => format!("{} (subaccount: {})", account.owner, account.subaccount.as_ref().map_or("none".to_string(), hex::encode)),
+        None => "the holder itself".to_string(),
+    }));
+    let keys = change_keys(&holder);
+    let seq = keys.last().map_or(0, |key| key.seq + 1);
+    HISTORY.with(|history| {
+        let mut history = history.borrow_mut();
+        history.insert(ChangeKey { holder, seq }, change);
+        for key in keys.iter().take((keys.len() + 1).saturating_sub(MAX_HISTORY_PER_HOLDER)) {
+            history.remove(key);
+        }
+    });
+}
+
+fn change_keys(holder: &Principal) -> Vec<ChangeKey> {
+    let start = ChangeKey { holder: *holder, seq: u64::MIN };
+    let end = ChangeKey { seq: u64::MAX, ..start };
+    HISTORY.with(|history| history.borrow().range(start..=end).map(|(key, _)| key).collect())
+}
+
+// Recipient changes of `holder`, oldest first
+pub fn history(holder: &Principal) -> Vec<RecipientChange> {
+    let start = ChangeKey { holder: *holder, seq: u64::MIN };
+    let end = ChangeKey { seq: u64::MAX, ..start };
+    HISTORY.with(|history| history.borrow().range(start..=end).map(|(_, change)| change).collect())
+}
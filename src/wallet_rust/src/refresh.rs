@@ -0,0 +1,130 @@
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+use crate::account_index;
+use crate::collections::{Collection, CollectionHolding, CollectionStandard};
+use crate::csv_loader::HolderInfo;
+use crate::gg_registry_interface::get_gg_registry_records;
+use crate::nft_registry_interface::{get_registry_daku_records, TokenIndex};
+
+// Unresolved owners listed per collection; the counts include all of them
+const MAX_LISTED_UNRESOLVED: usize = 50;
+
+// Outcome of reading one collection's registry
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CollectionRefreshReport {
+    pub collection: Principal,
+    pub tokens_attributed: u64,
+    pub tokens_unresolved: u64,
+    // Distinct owners that didn't resolve to a principal, and the first of them
+    pub owners_unresolved: u64,
+    pub unresolved_owners: Vec<String>,
+    // Set when getRegistry failed; previous counts for this collection were kept
+    pub error: Option<String>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Default, Debug)]
+pub struct RegistryRefreshReport {
+    pub holders: u64,
+    pub tokens_attributed: u64,
+    pub tokens_unresolved: u64,
+    pub collections: Vec<CollectionRefreshReport>,
+}
+
+// Call getRegistry once for a collection and return raw (token, owner) pairs
+async fn fetch_registry(collection: &Collection) -> Result<Vec<(TokenIndex, String)>, String> {
+    let result = match collection.standard {
+        CollectionStandard::AlbumTokens => get_gg_registry_records(collection.canister_id).await
+            .map(|records| records.into_iter().map(|r| (r.index, r.owner)).collect()),
+        CollectionStandard::DakuTokens | CollectionStandard::Ext => get_registry_daku_records(collection.canister_id).await
+            .map(|records| records.into_iter().map(|r| (r.index, r.owner)).collect()),
+    };
+
+    result.map_err(|(code, msg)| format!("getRegistry on {} failed: {:?} - {}", collection.canister_id, code, msg))
+}
+
+// Holder snapshot built from the registries, plus token ownership for every
+// collection whose registry was read
+pub struct RegistrySnapshot {
+    pub holders: HashMap<Principal, HolderInfo>,
+    pub tokens: Vec<(Principal, Vec<(TokenIndex, Principal)>)>,
+    pub report: RegistryRefreshReport,
+}
+
+// Build a complete holder snapshot from the registries of `collections`.
+// Collections whose registry can't be read keep the counts found in `previous`.
+pub async fn build_registry_snapshot(
+    collections: &[Collection],
+    previous: &HashMap<Principal, HolderInfo>,
+    current_time: u64,
+) -> RegistrySnapshot {
+    let mut holdings: HashMap<Principal, Vec<CollectionHolding>> = HashMap::new();
+    let mut tokens = Vec::new();
+    let mut report = RegistryRefreshReport::default();
+
+    for collection in collections {
+        match fetch_registry(collection).await {
+            Ok(records) => {
+                let total = records.len() as u64;
+                let mut resolved = Vec::with_capacity(records.len());
+                let mut unresolved_owners: BTreeSet<String> = BTreeSet::new();
+                for (index, owner) in records {
+                    match account_index::resolve_owner(&owner) {
+                        Some(principal) => resolved.push((index, principal)),
+                        None => {
+                            unresolved_owners.insert(owner);
+                        },
+                    }
+                }
+                let unresolved = total - resolved.len() as u64;
+
+                // Group tokens by owner
+                let mut counts: HashMap<Principal, u64> = HashMap::new();
+                for (_, owner) in &resolved {
+                    *counts.entry(*owner).or_insert(0) += 1;
+                }
+                tokens.push((collection.canister_id, resolved));
+                for (owner, count) in counts {
+                    holdings.entry(owner).or_default().push(CollectionHolding::new(collection.canister_id, count));
+                }
+
+                report.tokens_attributed += total - unresolved;
+                report.tokens_unresolved += unresolved;
+                report.collections.push(CollectionRefreshReport {
+                    collection: collection.canister_id,
+                    tokens_attributed: total - unresolved,
+                    tokens_unresolved: unresolved,
+                    owners_unresolved: unresolved_owners.len() as u64,
+                    unresolved_owners: unresolved_owners.into_iter().take(MAX_LISTED_UNRESOLVED).collect(),
+                    error: None,
+                });
+            },
+            Err(e) => {
+                ic_cdk::print(format!("Registry refresh for {} failed, keeping previous counts: {}", collection.name, e));
+                for (principal, info) in previous {
+                    if let Some(holding) = info.stale_holding(&collection.canister_id, &e, current_time) {
+                        holdings.entry(*principal).or_default().push(holding);
+                    }
+                }
+                report.collections.push(CollectionRefreshReport {
+                    collection: collection.canister_id,
+                    tokens_attributed: 0,
+                    tokens_unresolved: 0,
+                    owners_unresolved: 0,
+                    unresolved_owners: Vec::new(),
+                    error: Some(e),
+                });
+            }
+        }
+    }
+
+    account_index::set_last_unresolved(report.tokens_unresolved);
+
+    let holders: HashMap<Principal, HolderInfo> = holdings.into_iter()
+        .map(|(principal, holdings)| (principal, HolderInfo::from_holdings(holdings, current_time)))
+        .collect();
+    report.holders = holders.len() as u64;
+
+    RegistrySnapshot { holders, tokens, report }
+}
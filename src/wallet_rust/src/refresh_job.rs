@@ -0,0 +1,279 @@
+use candid::{CandidType, Principal};
+use ic_cdk::api::time;
+use ic_stable_structures::StableCell;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::time::Duration;
+
+use crate::errors::WalletError;
+use crate::refresh::RegistryRefreshReport;
+use crate::scheduler::{self, RefreshMode, RefreshOutcome, RefreshTrigger};
+use crate::state::{self, Memory, REFRESH_JOB_MEMORY_ID};
+
+// Holders per batch. Each holder costs a couple of inter-canister calls, so a batch
+// stays well inside one message's instruction budget even with retries.
+pub const HOLDER_BATCH_SIZE: usize = 25;
+
+// A job that hasn't made progress for this long is treated as dead and may be replaced
+const STALE_JOB_NS: u64 = 30 * 60 * 1_000_000_000;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum RefreshJobStatus {
+    Running,
+    Completed,
+    Cancelled,
+    // Stopped by a trap or upgrade; per-holder jobs can be resumed from their cursor
+    Interrupted(String),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RefreshJob {
+    pub job_id: u64,
+    pub trigger: RefreshTrigger,
+    pub mode: RefreshMode,
+    pub status: RefreshJobStatus,
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    // Last principal processed by a per-holder job; the next batch starts after it
+    pub cursor: Option<Principal>,
+    pub total: u64,
+    pub processed: u64,
+    pub updated: u64,
+    pub last_progress_at: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Default, Debug)]
+struct RefreshJobState {
+    next_job_id: u64,
+    current: Option<RefreshJob>,
+}
+
+state::impl_candid_storable!(RefreshJobState, 1024);
+
+thread_local! {
+    static JOB: RefCell<StableCell<RefreshJobState, Memory>> = RefCell::new(
+        StableCell::init(state::memory(REFRESH_JOB_MEMORY_ID), RefreshJobState::default())
+            .expect("Failed to init refresh job cell")
+    );
+
+    // Set while a batch is awaiting calls, so a stray timer can't start a second one
+    static BATCH_IN_FLIGHT: Cell<bool> = const { Cell::new(false) };
+}
+
+pub fn current_job() -> Option<RefreshJob> {
+    JOB.with(|job| job.borrow().get().current.clone())
+}
+
+fn update_job<F: FnOnce(&mut RefreshJob)>(job_id: u64, f: F) {
+    JOB.with(|job| {
+        let mut cell = job.borrow_mut();
+        let mut value = cell.get().clone();
+        if let Some(current) = value.current.as_mut().filter(|current| current.job_id == job_id) {
+            f(current);
+            cell.set(value).expect("Failed to write refresh job");
+        }
+    });
+}
+
+fn is_running(job: &RefreshJob) -> bool {
+    job.status == RefreshJobStatus::Running
+        && time().saturating_sub(job.last_progress_at) < STALE_JOB_NS
+}
+
+// Fail with RefreshInProgress while another refresh holds the lock
+pub fn ensure_idle() -> Result<(), WalletError> {
+    match current_job() {
+        Some(job) if is_running(&job) => Err(WalletError::RefreshInProgress),
+        _ => Ok(()),
+    }
+}
+
+// Take the refresh lock by recording a new running job
+fn begin(trigger: RefreshTrigger, mode: RefreshMode, total: u64) -> Result<RefreshJob, WalletError> {
+    ensure_idle()?;
+    let now = time();
+    JOB.with(|job| {
+        let mut cell = job.borrow_mut();
+        let mut value = cell.get().clone();
+        value.next_job_id += 1;
+        let started = RefreshJob {
+            job_id: value.next_job_id,
+            trigger,
+            mode,
+            status: RefreshJobStatus::Running,
+            started_at: now,
+            finished_at: None,
+            cursor: None,
+            total,
+            processed: 0,
+            updated: 0,
+            last_progress_at: now,
+        };
+        value.current = Some(started.clone());
+        cell.set(value).expect("Failed to write refresh job");
+        Ok(started)
+    })
+}
+
+fn finish(job_id: u64, status: RefreshJobStatus) {
+    let now = time();
+    update_job(job_id, |job| {
+        job.status = status;
+        job.finished_at = Some(now);
+        job.last_progress_at = now;
+    });
+}
+
+// Marks the job interrupted if the holder future is dropped before finishing,
+// which is what happens when a trap unwinds an awaiting refresh
+struct JobGuard {
+    job_id: u64,
+    done: bool,
+}
+
+impl JobGuard {
+    fn complete(mut self, status: RefreshJobStatus) {
+        finish(self.job_id, status);
+        self.done = true;
+    }
+
+    // The job continues in a later message
+    fn release(mut self) {
+        self.done = true;
+    }
+}
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        if !self.done {
+            finish(self.job_id, RefreshJobStatus::Interrupted("refresh trapped while awaiting a call".to_string()));
+        }
+    }
+}
+
+// Refresh every holder from getRegistry while holding the refresh lock
+pub async fn run_registry(trigger: RefreshTrigger) -> Result<RegistryRefreshReport, WalletError> {
+    let job = begin(trigger, RefreshMode::Registry, 0)?;
+    let guard = JobGuard { job_id: job.job_id, done: false };
+
+    let report = crate::run_registry_refresh().await;
+    update_job(job.job_id, |job| {
+        job.total = report.holders;
+        job.processed = report.holders;
+        job.updated = report.holders;
+    });
+    guard.complete(RefreshJobStatus::Completed);
+
+    scheduler::record_run(trigger, RefreshMode::Registry, job.started_at, scheduler::registry_outcome(&report));
+    Ok(report)
+}
+
+// Start a per-holder refresh that works through the holder set in batches
+pub fn start_holder_job(trigger: RefreshTrigger, total: u64) -> Result<RefreshJob, WalletError> {
+    let job = begin(trigger, RefreshMode::PerHolder, total)?;
+    // Results from a cancelled or abandoned job must not leak into this one
+    crate::clear_staged_holders();
+    ic_cdk::print(format!("Refresh job {} started for {} holders", job.job_id, total));
+    schedule_next_batch();
+    Ok(job)
+}
+
+// Continue an interrupted per-holder job from its cursor
+pub fn resume() -> Result<RefreshJob, WalletError> {
+    let job = match current_job() {
+        Some(job) if job.mode == RefreshMode::PerHolder && matches!(job.status, RefreshJobStatus::Interrupted(_)) => job,
+        _ => return Err(WalletError::InvalidArgument("no interrupted per-holder refresh job to resume".to_string())),
+    };
+
+    let now = time();
+    update_job(job.job_id, |job| {
+        job.status = RefreshJobStatus::Running;
+        job.finished_at = None;
+        job.last_progress_at = now;
+    });
+    schedule_next_batch();
+    Ok(current_job().expect("job was just resumed"))
+}
+
+pub fn cancel() -> Option<RefreshJob> {
+    let job = current_job().filter(|job| job.status == RefreshJobStatus::Running)?;
+    finish(job.job_id, RefreshJobStatus::Cancelled);
+    current_job()
+}
+
+// Called from post_upgrade: pending batch timers were dropped with the old module
+pub fn resume_after_upgrade() {
+    let Some(job) = current_job().filter(|job| job.status == RefreshJobStatus::Running) else {
+        return;
+    };
+    match job.mode {
+        RefreshMode::PerHolder => {
+            ic_cdk::print(format!("Resuming refresh job {} after upgrade at {}/{}", job.job_id, job.processed, job.total));
+            schedule_next_batch();
+        },
+        RefreshMode::Registry => {
+            finish(job.job_id, RefreshJobStatus::Interrupted("canister upgraded during refresh".to_string()));
+        },
+    }
+}
+
+fn schedule_next_batch() {
+    ic_cdk_timers::set_timer(Duration::ZERO, || ic_cdk::spawn(run_next_batch()));
+}
+
+// Clears BATCH_IN_FLIGHT however the batch ends
+struct BatchGuard;
+
+impl BatchGuard {
+    fn acquire() -> Option<BatchGuard> {
+        if BATCH_IN_FLIGHT.with(|in_flight| in_flight.replace(true)) {
+            None
+        } else {
+            Some(BatchGuard)
+        }
+    }
+}
+
+impl Drop for BatchGuard {
+    fn drop(&mut self) {
+        BATCH_IN_FLIGHT.with(|in_flight| in_flight.set(false));
+    }
+}
+
+async fn run_next_batch() {
+    let Some(_batch) = BatchGuard::acquire() else {
+        return;
+    };
+    let Some(job) = current_job().filter(|job| job.mode == RefreshMode::PerHolder && job.status == RefreshJobStatus::Running) else {
+        return;
+    };
+
+    let principals = crate::holder_batch_after(job.cursor, HOLDER_BATCH_SIZE);
+    if principals.is_empty() {
+        crate::commit_staged_holders(time());
+        finish(job.job_id, RefreshJobStatus::Completed);
+        ic_cdk::print(format!("Refresh job {} completed, updated {} of {} holders", job.job_id, job.updated, job.processed));
+        scheduler::record_run(job.trigger, RefreshMode::PerHolder, job.started_at, RefreshOutcome::Ok { holders: job.updated, tokens_unresolved: None });
+        return;
+    }
+
+    let guard = JobGuard { job_id: job.job_id, done: false };
+    let updated = crate::refresh_holder_batch(&principals).await;
+
+    // The job may have been cancelled while this batch was awaiting calls
+    let still_running = current_job()
+        .map(|current| current.job_id == job.job_id && current.status == RefreshJobStatus::Running)
+        .unwrap_or(false);
+    let now = time();
+    update_job(job.job_id, |job| {
+        job.cursor = principals.last().copied();
+        job.processed += principals.len() as u64;
+        job.updated += updated;
+        job.last_progress_at = now;
+    });
+    guard.release();
+
+    if still_running {
+        schedule_next_batch();
+    }
+}
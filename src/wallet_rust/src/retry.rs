@@ -0,0 +1,107 @@
+use ic_cdk::api::call::{CallResult, RejectionCode};
+use ic_cdk::api::time;
+use std::future::Future;
+
+// Name of the no-op update method we call on ourselves to let time pass between attempts
+pub const BACKOFF_TICK_METHOD: &str = "retry_backoff_tick";
+
+// A single self-call takes about one round, so this bounds one sleep to a few dozen seconds
+const MAX_TICKS_PER_SLEEP: u32 = 32;
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    // Total number of calls, including the first one
+    pub max_attempts: u8,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+    // Per-code cap on attempts; codes that aren't listed are never retried
+    pub code_rules: &'static [(RejectionCode, u8)],
+}
+
+// Transient system errors are worth the full budget. A CanisterError is usually
+// deterministic, but a stopping or upgrading collection canister recovers, so allow one retry.
+// SysFatal, DestinationInvalid and CanisterReject will fail the same way again.
+const DEFAULT_CODE_RULES: &[(RejectionCode, u8)] = &[
+    (RejectionCode::SysTransient, u8::MAX),
+    (RejectionCode::CanisterError, 2),
+];
+
+// Used for the per-holder `tokens` queries against collection canisters
+pub const TOKENS_QUERY_POLICY: RetryPolicy = RetryPolicy {
+    max_attempts: 3,
+    base_delay_ms: 500,
+    max_delay_ms: 5_000,
+    code_rules: DEFAULT_CODE_RULES,
+};
+
+impl RetryPolicy {
+    fn attempts_for(&self, code: RejectionCode) -> u8 {
+        self.code_rules.iter()
+            .find(|(rule_code, _)| *rule_code == code)
+            .map(|(_, attempts)| (*attempts).min(self.max_attempts))
+            .unwrap_or(1)
+    }
+
+    // Exponential backoff capped at max_delay_ms, with "equal jitter": half fixed, half random
+    fn delay_ms(&self, attempt: u8) -> u64 {
+        let exponent = u32::from(attempt.saturating_sub(1)).min(16);
+        let delay = self.base_delay_ms.saturating_mul(1 << exponent).min(self.max_delay_ms);
+        let half = delay / 2;
+        half + jitter(attempt) % (half + 1)
+    }
+}
+
+// Canister code has no cheap entropy source; mixing the current time is enough to
+// keep parallel retries from lining up on the same round
+fn jitter(attempt: u8) -> u64 {
+    let mut x = time() ^ u64::from(attempt).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+// Wait until at least `delay_ms` has passed. time() only advances between messages,
+// so we yield by calling ourselves until the deadline is reached. The await stays in the
+// caller's call context, which a timer callback could not give us.
+async fn sleep(delay_ms: u64) -> CallResult<()> {
+    let deadline = time().saturating_add(delay_ms.saturating_mul(1_000_000));
+    let mut ticks = 0;
+    while time() < deadline && ticks < MAX_TICKS_PER_SLEEP {
+        ic_cdk::call::<(), ()>(ic_cdk::id(), BACKOFF_TICK_METHOD, ()).await?;
+        ticks += 1;
+    }
+    Ok(())
+}
+
+// Run `operation` until it succeeds, the rejection code isn't retryable or the policy's
+// attempts are used up. The last error is returned unchanged so callers can still fall back.
+pub async fn with_retry<T, F, Fut>(policy: &RetryPolicy, label: &str, operation: F) -> CallResult<T>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = CallResult<T>>,
+{
+    let mut attempt: u8 = 1;
+    loop {
+        let (code, msg) = match operation().await {
+            Ok(result) => return Ok(result),
+            Err(e) => e,
+        };
+
+        if attempt >= policy.attempts_for(code) {
+            if attempt > 1 {
+                ic_cdk::print(format!("{} failed after {} attempts: {:?} - {}", label, attempt, code, msg));
+            }
+            return Err((code, msg));
+        }
+
+        let delay_ms = policy.delay_ms(attempt);
+        ic_cdk::print(format!("{} attempt {} failed ({:?} - {}), retrying in {}ms", label, attempt, code, msg, delay_ms));
+
+        // Self-calls aren't possible from a query; give up with the original error in that case
+        if let Err((tick_code, tick_msg)) = sleep(delay_ms).await {
+            ic_cdk::print(format!("{} backoff unavailable: {:?} - {}", label, tick_code, tick_msg));
+            return Err((code, msg));
+        }
+        attempt += 1;
+    }
+}
@@ -0,0 +1,292 @@
+use candid::{CandidType, Principal};
+use ic_stable_structures::StableCell;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::collections::{self, DEFAULT_REWARD_WEIGHT_BPS};
+use crate::errors::WalletError;
+use crate::recipients::Account;
+use crate::state::{self, Memory, REWARD_POLICY_MEMORY_ID};
+
+const BPS: u128 = 10_000;
+const MAX_MULTIPLIER_BPS: u32 = 100_000;
+const MAX_TIERS: usize = 16;
+const MAX_SET_RULES: usize = 8;
+const MAX_SET_RULE_NAME_LEN: usize = 64;
+
+// Multiplier applied to holders with at least `min_tokens` tokens across all collections
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RewardTier {
+    pub min_tokens: u64,
+    pub multiplier_bps: u32,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SetRequirement {
+    pub collection: Principal,
+    pub min_count: u64,
+}
+
+// Cross-collection bonus, e.g. "at least 1 Daku and 1 GG Album gives 1.25x"
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SetRule {
+    pub name: String,
+    pub requirements: Vec<SetRequirement>,
+    pub multiplier_bps: u32,
+}
+
+// The set rule a holder qualifies for
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SetBonus {
+    pub rule: String,
+    pub multiplier_bps: u32,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PoolSplit {
+    // Each holder's share follows their weighted score
+    Proportional,
+    // Every eligible holder gets the same share; scores only decide eligibility
+    Equal,
+}
+
+// How the pool is divided. Per-collection weights come from each Collection's reward_weight_bps.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RewardPolicy {
+    pub split: PoolSplit,
+    // The highest tier a holder reaches applies; holders below every tier get 1.0x
+    pub tiers: Vec<RewardTier>,
+    // Holders with fewer tokens than this across all collections get nothing
+    pub min_tokens: u64,
+    // Most a single wallet can receive; the excess is shared among the other holders
+    pub max_per_wallet: Option<u64>,
+    // Score holdings by their time-weighted average over this window instead of the
+    // current snapshot (see get_weighted_holdings)
+    pub weighting_window_secs: Option<u64>,
+    // When several set rules match, only the one with the largest multiplier applies.
+    // It stacks with the tier multiplier.
+    pub set_rules: Option<Vec<SetRule>>,
+}
+
+impl Default for RewardPolicy {
+    fn default() -> Self {
+        RewardPolicy {
+            split: PoolSplit::Proportional,
+            tiers: Vec::new(),
+            min_tokens: 1,
+            max_per_wallet: None,
+            weighting_window_secs: None,
+            set_rules: None,
+        }
+    }
+}
+
+state::impl_candid_storable!(RewardPolicy, 2048);
+
+// Holdings the plan was computed from
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum RewardBasis {
+    Snapshot { snapshot_id: Option<u64> },
+    TimeWeighted { window_start: u64, window_end: u64 },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RewardEntry {
+    pub principal: Principal,
+    // Where the amount is paid; the holder's own account unless they delegated
+    pub recipient: Account,
+    // Wallets linked to `principal` whose holdings are included below
+    pub linked_wallets: Vec<Principal>,
+    // Tokens held across all collections, in thousandths
+    pub tokens_milli: u64,
+    // Tokens after collection weights and the tier multiplier, in thousandths
+    pub score_milli: u64,
+    pub set_bonus: Option<SetBonus>,
+    pub amount: u64,
+    pub capped: bool,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RewardPlan {
+    pub pool_amount: u64,
+    pub distributed: u64,
+    // Rounding dust, or the whole pool when every holder is capped or ineligible
+    pub undistributed: u64,
+    pub basis: RewardBasis,
+    pub policy: RewardPolicy,
+    pub entries: Vec<RewardEntry>,
+    // sha256 over everything above; equal hashes mean identical payouts
+    pub plan_hash: String,
+}
+
+// A holder's amount per collection in thousandths of a token, linked wallets included
+pub struct HolderTokens {
+    pub principal: Principal,
+    pub recipient: Account,
+    pub linked_wallets: Vec<Principal>,
+    pub holdings: Vec<(Principal, u64)>,
+}
+
+thread_local! {
+    static POLICY: RefCell<StableCell<RewardPolicy, Memory>> = RefCell::new(
+        StableCell::init(state::memory(REWARD_POLICY_MEMORY_ID), RewardPolicy::default())
+            .expect("Failed to init reward policy cell")
+    );
+}
+
+pub fn policy() -> RewardPolicy {
+    POLICY.with(|policy| policy.borrow().get().clone())
+}
+
+pub fn set_policy(mut policy: RewardPolicy) -> Result<RewardPolicy, WalletError> {
+    if policy.tiers.len() > MAX_TIERS {
+        return Err(WalletError::InvalidArgument(format!("at most {} reward tiers are allowed", MAX_TIERS)));
+    }
+    if let Some(tier) = policy.tiers.iter().find(|tier| tier.multiplier_bps > MAX_MULTIPLIER_BPS) {
+        return Err(WalletError::InvalidArgument(format!(
+            "tier multiplier {} bps exceeds the maximum of {}", tier.multiplier_bps, MAX_MULTIPLIER_BPS
+        )));
+    }
+    policy.tiers.sort_by_key(|tier| tier.min_tokens);
+    if policy.tiers.windows(2).any(|pair| pair[0].min_tokens == pair[1].min_tokens) {
+        return Err(WalletError::InvalidArgument("two reward tiers share the same min_tokens".to_string()));
+    }
+    for rule in policy.set_rules.iter().flatten() {
+        if rule.name.trim().is_empty() || rule.name.len() > MAX_SET_RULE_NAME_LEN {
+            return Err(WalletError::InvalidArgument(format!(
+                "set rule names must be 1 to {} characters", MAX_SET_RULE_NAME_LEN
+            )));
+        }
+        if rule.requirements.is_empty() {
+            return Err(WalletError::InvalidArgument(format!("set rule '{}' has no requirements", rule.name)));
+        }
+        if rule.multiplier_bps > MAX_MULTIPLIER_BPS {
+            return Err(WalletError::InvalidArgument(format!(
+                "set rule '{}' multiplier {} bps exceeds the maximum of {}", rule.name, rule.multiplier_bps, MAX_MULTIPLIER_BPS
+            )));
+        }
+        if let Some(missing) = rule.requirements.iter().find(|r| collections::get_collection(&r.collection).is_none()) {
+            return Err(WalletError::CollectionNotFound(missing.collection));
+        }
+    }
+    if policy.set_rules.as_ref().is_some_and(|rules| rules.len() > MAX_SET_RULES) {
+        return Err(WalletError::InvalidArgument(format!("at most {} set rules are allowed", MAX_SET_RULES)));
+    }
+    if policy.weighting_window_secs == Some(0) {
+        return Err(WalletError::InvalidArgument("weighting_window_secs must be greater than 0".to_string()));
+    }
+    POLICY.with(|cell| cell.borrow_mut().set(policy.clone()).expect("Failed to write reward policy"));
+    Ok(policy)
+}
+
+fn tier_multiplier_bps(policy: &RewardPolicy, tokens: u64) -> u128 {
+    policy.tiers.iter()
+        .rev()
+        .find(|tier| tokens >= tier.min_tokens)
+        .map_or(BPS, |tier| tier.multiplier_bps as u128)
+}
+
+// The best set rule satisfied by `holdings` (collection, thousandths of a token)
+pub fn set_bonus(policy: &RewardPolicy, holdings: &[(Principal, u64)]) -> Option<SetBonus> {
+    let held = |collection: &Principal| holdings.iter()
+        .filter(|(c, _)| c == collection)
+        .map(|(_, milli)| *milli as u128)
+        .sum::<u128>();
+    policy.set_rules.iter().flatten()
+        .filter(|rule| rule.requirements.iter().all(|r| held(&r.collection) >= r.min_count as u128 * 1000))
+        .max_by_key(|rule| rule.multiplier_bps)
+        .map(|rule| SetBonus { rule: rule.name.clone(), multiplier_bps: rule.multiplier_bps })
+}
+
+// Score every holder under `policy` and divide `pool_amount` between them
+pub fn compute_plan(policy: RewardPolicy, basis: RewardBasis, holders: Vec<HolderTokens>, pool_amount: u64) -> RewardPlan {
+    let weights: HashMap<Principal, u128> = collections::list_collections().into_iter()
+        .map(|collection| (collection.canister_id, collection.reward_weight_bps as u128))
+        .collect();
+
+    let mut entries: Vec<RewardEntry> = holders.into_iter()
+        .filter_map(|holder| {
+            let tokens_milli: u128 = holder.holdings.iter().map(|(_, milli)| *milli as u128).sum();
+            if tokens_milli < policy.min_tokens as u128 * 1000 {
+                return None;
+            }
+            // Collections that are no longer registered keep the default weight
+            let weighted: u128 = holder.holdings.iter()
+                .map(|(collection, milli)| {
+                    let weight = weights.get(collection).copied().unwrap_or(DEFAULT_REWARD_WEIGHT_BPS as u128);
+                    *milli as u128 * weight / BPS
+                })
+                .sum();
+            let bonus = set_bonus(&policy, &holder.holdings);
+            let set_multiplier = bonus.as_ref().map_or(BPS, |bonus| bonus.multiplier_bps as u128);
+            let score = weighted * tier_multiplier_bps(&policy, (tokens_milli / 1000) as u64) / BPS * set_multiplier / BPS;
+            (score > 0).then_some(RewardEntry {
+                principal: holder.principal,
+                recipient: holder.recipient,
+                linked_wallets: holder.linked_wallets,
+                tokens_milli: tokens_milli as u64,
+                score_milli: score as u64,
+                set_bonus: bonus,
+                amount: 0,
+                capped: false,
+            })
+        })
+        .collect();
+    entries.sort_by_key(|entry| entry.principal);
+
+    let distributed = allocate(&policy, &mut entries, pool_amount);
+    let mut plan = RewardPlan {
+        pool_amount,
+        distributed,
+        undistributed: pool_amount - distributed,
+        basis,
+        policy,
+        entries,
+        plan_hash: String::new(),
+    };
+    plan.plan_hash = hex::encode(Sha256::digest(candid::encode_one(&plan).expect("Failed to encode reward plan")));
+    plan
+}
+
+// Split the pool by score (or equally), capping wallets at max_per_wallet and sharing
+// what the capped wallets can't take among the rest. Returns the total handed out.
+fn allocate(policy: &RewardPolicy, entries: &mut [RewardEntry], pool_amount: u64) -> u64 {
+    let cap = policy.max_per_wallet.map_or(u128::MAX, |cap| cap as u128);
+    let mut remaining = pool_amount as u128;
+
+    loop {
+        let open: Vec<usize> = (0..entries.len()).filter(|i| !entries[*i].capped).collect();
+        let total_score: u128 = open.iter()
+            .map(|i| match policy.split {
+                PoolSplit::Proportional => entries[*i].score_milli as u128,
+                PoolSplit::Equal => 1,
+            })
+            .sum();
+        if open.is_empty() || total_score == 0 || remaining == 0 {
+            break;
+        }
+
+        let shares: Vec<(usize, u128)> = open.iter()
+            .map(|i| (*i, match policy.split {
+                PoolSplit::Proportional => remaining * entries[*i].score_milli as u128 / total_score,
+                PoolSplit::Equal => remaining / total_score,
+            }))
+            .collect();
+        if shares.iter().all(|(_, share)| *share < cap) {
+            for (i, share) in shares {
+                entries[i].amount = share as u64;
+            }
+            break;
+        }
+        // Fix the capped wallets and share the rest again among the others
+        for (i, _) in shares.into_iter().filter(|(_, share)| *share >= cap) {
+            entries[i].amount = cap as u64;
+            entries[i].capped = true;
+            remaining -= cap;
+        }
+    }
+
+    entries.iter().map(|entry| entry.amount).sum()
+}
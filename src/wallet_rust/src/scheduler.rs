@@ -0,0 +1,197 @@
+use candid::CandidType;
+use ic_cdk::api::time;
+use ic_cdk_timers::TimerId;
+use ic_stable_structures::StableCell;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::time::Duration;
+
+use crate::refresh::RegistryRefreshReport;
+use crate::refresh_job;
+use crate::state::{self, Memory, REFRESH_SCHEDULE_MEMORY_ID};
+
+// How many finished runs are kept for get_refresh_schedule
+const MAX_RECORDED_RUNS: usize = 20;
+
+// Refresh intervals below this would spend most of the canister's cycles on getRegistry
+pub const MIN_REFRESH_INTERVAL_SECS: u64 = 5 * 60;
+
+// Longest supported interval (a year); keeps the nanosecond maths far from overflowing
+pub const MAX_REFRESH_INTERVAL_SECS: u64 = 365 * 24 * 60 * 60;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RefreshMode {
+    // One getRegistry call per collection (refresh_holders_from_registry)
+    Registry,
+    // Query every known holder individually (update_all_holders)
+    PerHolder,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RefreshTrigger {
+    Timer,
+    Manual,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum RefreshOutcome {
+    // tokens_unresolved is only set by registry refreshes
+    Ok { holders: u64, tokens_unresolved: Option<u64> },
+    Err(String),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RefreshRun {
+    pub trigger: RefreshTrigger,
+    pub mode: RefreshMode,
+    pub started_at: u64,
+    pub duration_ns: u64,
+    pub outcome: RefreshOutcome,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RefreshSchedule {
+    // 0 means no automatic refresh has been configured
+    pub interval_secs: u64,
+    pub paused: bool,
+    pub mode: RefreshMode,
+    // When the current timer was armed; runs fire every interval after that
+    pub armed_at: Option<u64>,
+    // Derived from armed_at on read, never stored
+    pub next_run_at: Option<u64>,
+    pub recent_runs: Vec<RefreshRun>,
+}
+
+impl Default for RefreshSchedule {
+    fn default() -> Self {
+        RefreshSchedule {
+            interval_secs: 0,
+            paused: false,
+            mode: RefreshMode::Registry,
+            armed_at: None,
+            next_run_at: None,
+            recent_runs: Vec::new(),
+        }
+    }
+}
+
+state::impl_candid_storable!(RefreshSchedule, 8192);
+
+thread_local! {
+    static SCHEDULE: RefCell<StableCell<RefreshSchedule, Memory>> = RefCell::new(
+        StableCell::init(state::memory(REFRESH_SCHEDULE_MEMORY_ID), RefreshSchedule::default())
+            .expect("Failed to init refresh schedule")
+    );
+
+    // Timer handles don't survive upgrades, so they stay on the heap and are re-armed in post_upgrade
+    static TIMER: Cell<Option<TimerId>> = const { Cell::new(None) };
+}
+
+pub fn get_schedule() -> RefreshSchedule {
+    let mut schedule = SCHEDULE.with(|schedule| schedule.borrow().get().clone());
+    // A disabled schedule has no next run, even if it is still marked as armed
+    let interval_ns = schedule.interval_secs.saturating_mul(1_000_000_000);
+    schedule.next_run_at = schedule.armed_at.filter(|_| interval_ns > 0).map(|armed_at| {
+        let elapsed_intervals = time().saturating_sub(armed_at) / interval_ns;
+        armed_at.saturating_add((elapsed_intervals + 1).saturating_mul(interval_ns))
+    });
+    schedule
+}
+
+fn update_schedule<F: FnOnce(&mut RefreshSchedule)>(f: F) {
+    SCHEDULE.with(|schedule| {
+        let mut cell = schedule.borrow_mut();
+        let mut value = cell.get().clone();
+        f(&mut value);
+        cell.set(value).expect("Failed to write refresh schedule");
+    });
+}
+
+fn clear_timer() {
+    if let Some(timer_id) = TIMER.with(|timer| timer.take()) {
+        ic_cdk_timers::clear_timer(timer_id);
+    }
+}
+
+// (Re-)arm the interval timer from the stored schedule; called after every change and in post_upgrade
+pub fn arm() {
+    clear_timer();
+    let schedule = get_schedule();
+
+    if schedule.paused || schedule.interval_secs == 0 {
+        update_schedule(|s| s.armed_at = None);
+        return;
+    }
+
+    let interval = Duration::from_secs(schedule.interval_secs);
+    let timer_id = ic_cdk_timers::set_timer_interval(interval, || {
+        ic_cdk::spawn(run_scheduled_refresh());
+    });
+    TIMER.with(|timer| timer.set(Some(timer_id)));
+
+    let now = time();
+    update_schedule(|s| s.armed_at = Some(now));
+}
+
+pub fn set_interval(interval_secs: u64, mode: Option<RefreshMode>) {
+    update_schedule(|s| {
+        s.interval_secs = interval_secs;
+        s.paused = false;
+        // The old timer's start no longer applies; arm() sets it again if there is a timer
+        s.armed_at = None;
+        if let Some(mode) = mode {
+            s.mode = mode;
+        }
+    });
+    arm();
+}
+
+pub fn pause() {
+    update_schedule(|s| s.paused = true);
+    arm();
+}
+
+// Store the outcome of a finished refresh, whether it came from the timer or an admin call
+pub fn record_run(trigger: RefreshTrigger, mode: RefreshMode, started_at: u64, outcome: RefreshOutcome) {
+    let run = RefreshRun {
+        trigger,
+        mode,
+        started_at,
+        duration_ns: time().saturating_sub(started_at),
+        outcome,
+    };
+    ic_cdk::print(format!("Refresh run finished: {:?}", run));
+
+    update_schedule(|s| {
+        s.recent_runs.push(run);
+        if s.recent_runs.len() > MAX_RECORDED_RUNS {
+            let excess = s.recent_runs.len() - MAX_RECORDED_RUNS;
+            s.recent_runs.drain(..excess);
+        }
+    });
+}
+
+// Runs are recorded by the refresh job once it finishes
+async fn run_scheduled_refresh() {
+    let result = match get_schedule().mode {
+        RefreshMode::Registry => refresh_job::run_registry(RefreshTrigger::Timer).await.map(|_| ()),
+        RefreshMode::PerHolder => crate::start_holder_refresh(RefreshTrigger::Timer).map(|_| ()),
+    };
+
+    // A slow refresh can outlast the interval; never stack runs on top of each other
+    if let Err(e) = result {
+        ic_cdk::print(format!("Scheduled refresh skipped: {:?}", e));
+    }
+}
+
+// A registry refresh only fails outright when no collection could be read
+pub fn registry_outcome(report: &RegistryRefreshReport) -> RefreshOutcome {
+    let errors: Vec<&str> = report.collections.iter()
+        .filter_map(|c| c.error.as_deref())
+        .collect();
+    if !report.collections.is_empty() && errors.len() == report.collections.len() {
+        RefreshOutcome::Err(errors.join("; "))
+    } else {
+        RefreshOutcome::Ok { holders: report.holders, tokens_unresolved: Some(report.tokens_unresolved) }
+    }
+}
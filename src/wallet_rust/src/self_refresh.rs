@@ -0,0 +1,103 @@
+use candid::{CandidType, Principal};
+use ic_stable_structures::StableCell;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::errors::WalletError;
+use crate::state::{self, Memory, SELF_REFRESH_CONFIG_MEMORY_ID};
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+// Limits on refresh_my_holdings. Admin refreshes are not counted.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct SelfRefreshConfig {
+    pub enabled: bool,
+    // Minimum time between two refreshes by the same principal
+    pub cooldown_secs: u64,
+    // At most max_per_window refreshes across all callers in each window
+    pub window_secs: u64,
+    pub max_per_window: u32,
+}
+
+impl Default for SelfRefreshConfig {
+    fn default() -> Self {
+        SelfRefreshConfig {
+            enabled: true,
+            cooldown_secs: 15 * 60,
+            window_secs: 60 * 60,
+            max_per_window: 200,
+        }
+    }
+}
+
+state::impl_candid_storable!(SelfRefreshConfig, 128);
+
+// Usage only lives on the heap; an upgrade simply starts a fresh window
+#[derive(Default)]
+struct Usage {
+    window_start: u64,
+    used: u32,
+    last_refresh: HashMap<Principal, u64>,
+}
+
+thread_local! {
+    static CONFIG: RefCell<StableCell<SelfRefreshConfig, Memory>> = RefCell::new(
+        StableCell::init(state::memory(SELF_REFRESH_CONFIG_MEMORY_ID), SelfRefreshConfig::default())
+            .expect("Failed to init self refresh config cell")
+    );
+
+    static USAGE: RefCell<Usage> = RefCell::new(Usage::default());
+}
+
+pub fn config() -> SelfRefreshConfig {
+    CONFIG.with(|config| config.borrow().get().clone())
+}
+
+pub fn set_config(config: SelfRefreshConfig) -> Result<SelfRefreshConfig, WalletError> {
+    if config.window_secs == 0 {
+        return Err(WalletError::InvalidArgument("window_secs must be greater than 0".to_string()));
+    }
+    CONFIG.with(|cell| cell.borrow_mut().set(config.clone()).expect("Failed to write self refresh config"));
+    Ok(config)
+}
+
+fn secs_until(deadline: u64, now: u64) -> u64 {
+    deadline.saturating_sub(now).div_ceil(NANOS_PER_SEC)
+}
+
+// Charge one refresh to `caller` and the global budget, or say how long to wait.
+// Charged before any call is made, so a failed or concurrent refresh still counts.
+pub fn acquire(caller: Principal, now: u64) -> Result<(), WalletError> {
+    let config = config();
+    if !config.enabled {
+        return Err(WalletError::InvalidArgument("self-service refresh is disabled".to_string()));
+    }
+    let cooldown = config.cooldown_secs.saturating_mul(NANOS_PER_SEC);
+    let window = config.window_secs.saturating_mul(NANOS_PER_SEC);
+
+    USAGE.with(|usage| {
+        let mut usage = usage.borrow_mut();
+        if now.saturating_sub(usage.window_start) >= window {
+            usage.window_start = now;
+            usage.used = 0;
+            // Entries past their cooldown no longer matter
+            usage.last_refresh.retain(|_, last| now.saturating_sub(*last) < cooldown);
+        }
+
+        if let Some(last) = usage.last_refresh.get(&caller) {
+            let ready_at = last.saturating_add(cooldown);
+            if now < ready_at {
+                return Err(WalletError::CooldownActive { retry_after_secs: secs_until(ready_at, now) });
+            }
+        }
+        if usage.used >= config.max_per_window {
+            let next_window = usage.window_start.saturating_add(window);
+            return Err(WalletError::RefreshBudgetExhausted { retry_after_secs: secs_until(next_window, now) });
+        }
+
+        usage.used += 1;
+        usage.last_refresh.insert(caller, now);
+        Ok(())
+    })
+}
@@ -0,0 +1,250 @@
+use candid::{CandidType, Principal};
+use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+use ic_stable_structures::{BoundedStorable, DefaultMemoryImpl, StableBTreeMap, StableCell, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+
+use crate::collections;
+use crate::csv_loader::HolderInfo;
+use crate::NFTProgress;
+
+pub type Memory = VirtualMemory<DefaultMemoryImpl>;
+
+// Bump whenever the layout of a stored type changes and add a step to `migrate_schema`.
+// Fields added to a stored record after v1 must be `Option` so older entries still decode.
+//   v1: holder maps moved to stable memory
+//   v2: collection registry and per-collection holder counts
+pub const SCHEMA_VERSION: u32 = 2;
+
+// Memory ids are part of the stable layout - never reuse or renumber them
+const SCHEMA_VERSION_MEMORY_ID: MemoryId = MemoryId::new(0);
+const META_MEMORY_ID: MemoryId = MemoryId::new(1);
+const BALANCES_MEMORY_ID: MemoryId = MemoryId::new(2);
+const NFT_COUNTS_MEMORY_ID: MemoryId = MemoryId::new(3);
+const HOLDER_INFO_MEMORY_ID: MemoryId = MemoryId::new(4);
+const KNOWN_HOLDERS_MEMORY_ID: MemoryId = MemoryId::new(5);
+const DAKU_CSV_MEMORY_ID: MemoryId = MemoryId::new(6);
+const GG_CSV_MEMORY_ID: MemoryId = MemoryId::new(7);
+pub const ADMINS_MEMORY_ID: MemoryId = MemoryId::new(8);
+pub const COLLECTIONS_MEMORY_ID: MemoryId = MemoryId::new(9);
+pub const ACCOUNT_INDEX_MEMORY_ID: MemoryId = MemoryId::new(10);
+pub const ACCOUNT_INDEX_CONFIG_MEMORY_ID: MemoryId = MemoryId::new(11);
+pub const REFRESH_SCHEDULE_MEMORY_ID: MemoryId = MemoryId::new(12);
+pub const REFRESH_JOB_MEMORY_ID: MemoryId = MemoryId::new(13);
+const STAGED_HOLDER_INFO_MEMORY_ID: MemoryId = MemoryId::new(14);
+pub const TOKEN_OWNERS_MEMORY_ID: MemoryId = MemoryId::new(15);
+pub const HOLDER_TOKENS_MEMORY_ID: MemoryId = MemoryId::new(16);
+pub const DATA_QUALITY_MEMORY_ID: MemoryId = MemoryId::new(17);
+pub const HISTORY_SNAPSHOTS_MEMORY_ID: MemoryId = MemoryId::new(18);
+pub const HISTORY_HOLDERS_MEMORY_ID: MemoryId = MemoryId::new(19);
+pub const HISTORY_RETENTION_MEMORY_ID: MemoryId = MemoryId::new(20);
+pub const REWARD_POLICY_MEMORY_ID: MemoryId = MemoryId::new(21);
+pub const EXCLUSIONS_MEMORY_ID: MemoryId = MemoryId::new(22);
+pub const EXCLUSION_CONFIG_MEMORY_ID: MemoryId = MemoryId::new(23);
+pub const RECIPIENTS_MEMORY_ID: MemoryId = MemoryId::new(24);
+pub const RECIPIENT_HISTORY_MEMORY_ID: MemoryId = MemoryId::new(25);
+pub const WALLET_LINKS_MEMORY_ID: MemoryId = MemoryId::new(26);
+pub const LINK_PROPOSALS_MEMORY_ID: MemoryId = MemoryId::new(27);
+pub const SELF_REFRESH_CONFIG_MEMORY_ID: MemoryId = MemoryId::new(28);
+pub const HISTORY_CHANGES_MEMORY_ID: MemoryId = MemoryId::new(29);
+pub const HISTORY_CHANGE_INDEX_MEMORY_ID: MemoryId = MemoryId::new(30);
+
+// Principal wrapper so it can be used as a stable map key
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StablePrincipal(pub Principal);
+
+impl Storable for StablePrincipal {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.0.as_slice())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        StablePrincipal(Principal::from_slice(&bytes))
+    }
+}
+
+impl BoundedStorable for StablePrincipal {
+    const MAX_SIZE: u32 = 29;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// A principal in a fixed-width slot for composite keys: length byte, then the bytes
+// zero-padded to 29. The length goes first so every key of one principal sorts together.
+pub const PRINCIPAL_SLOT: usize = 30;
+
+pub fn write_principal(bytes: &mut Vec<u8>, principal: &Principal) {
+    let slice = principal.as_slice();
+    bytes.push(slice.len() as u8);
+    bytes.extend_from_slice(slice);
+    bytes.resize(bytes.len() + PRINCIPAL_SLOT - 1 - slice.len(), 0);
+}
+
+pub fn read_principal(bytes: &[u8]) -> Principal {
+    let len = bytes[0] as usize;
+    Principal::from_slice(&bytes[1..1 + len])
+}
+
+// Scalar bookkeeping that used to live in separate thread_local cells
+#[derive(CandidType, Serialize, Deserialize, Clone, Default, Debug)]
+pub struct CanisterMeta {
+    pub csv_data_loaded: bool,
+    pub last_bulk_update: u64,
+    // Bumped every time HOLDER_INFO changes, so readers can tell which data they saw
+    pub snapshot_id: Option<u64>,
+    pub snapshot_at: Option<u64>,
+}
+
+// Candid-encoded storage for record types, bounded by `$max_size` bytes.
+// Sizes are fixed once a map has been created in stable memory, so leave headroom.
+macro_rules! impl_candid_storable {
+    ($type:ty, $max_size:expr) => {
+        impl ic_stable_structures::Storable for $type {
+            fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+                std::borrow::Cow::Owned(candid::encode_one(self).expect(concat!("Failed to encode ", stringify!($type))))
+            }
+
+            fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+                candid::decode_one::<$type>(bytes.as_ref()).expect(concat!("Failed to decode ", stringify!($type)))
+            }
+        }
+
+        impl ic_stable_structures::BoundedStorable for $type {
+            const MAX_SIZE: u32 = $max_size;
+            const IS_FIXED_SIZE: bool = false;
+        }
+    };
+}
+
+pub(crate) use impl_candid_storable;
+
+impl_candid_storable!(HolderInfo, 4096);
+impl_candid_storable!(NFTProgress, 256);
+impl_candid_storable!(CanisterMeta, 256);
+
+thread_local! {
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
+        RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+
+    static STORED_SCHEMA_VERSION: RefCell<StableCell<u32, Memory>> = RefCell::new(
+        StableCell::init(memory(SCHEMA_VERSION_MEMORY_ID), 0).expect("Failed to init schema version cell")
+    );
+
+    pub static META: RefCell<StableCell<CanisterMeta, Memory>> = RefCell::new(
+        StableCell::init(memory(META_MEMORY_ID), CanisterMeta::default()).expect("Failed to init meta cell")
+    );
+
+    pub static BALANCES: RefCell<StableBTreeMap<StablePrincipal, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(BALANCES_MEMORY_ID)));
+
+    pub static NFT_COUNTS: RefCell<StableBTreeMap<StablePrincipal, NFTProgress, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(NFT_COUNTS_MEMORY_ID)));
+
+    pub static HOLDER_INFO: RefCell<StableBTreeMap<StablePrincipal, HolderInfo, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(HOLDER_INFO_MEMORY_ID)));
+
+    // Per-holder refresh jobs write here and are copied into HOLDER_INFO in one message when done
+    pub static STAGED_HOLDER_INFO: RefCell<StableBTreeMap<StablePrincipal, HolderInfo, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(STAGED_HOLDER_INFO_MEMORY_ID)));
+
+    // We'll keep known holders as fallback but prioritize real data
+    pub static KNOWN_HOLDERS: RefCell<StableBTreeMap<StablePrincipal, HolderInfo, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(KNOWN_HOLDERS_MEMORY_ID)));
+
+    // Store CSV data for holders
+    pub static DAKU_CSV_DATA: RefCell<StableCell<String, Memory>> = RefCell::new(
+        StableCell::init(memory(DAKU_CSV_MEMORY_ID), String::new()).expect("Failed to init Daku CSV cell")
+    );
+    pub static GG_CSV_DATA: RefCell<StableCell<String, Memory>> = RefCell::new(
+        StableCell::init(memory(GG_CSV_MEMORY_ID), String::new()).expect("Failed to init GG CSV cell")
+    );
+}
+
+// Hand out a virtual memory region for a stable structure
+pub fn memory(id: MemoryId) -> Memory {
+    MEMORY_MANAGER.with(|manager| manager.borrow().get(id))
+}
+
+pub fn get_meta() -> CanisterMeta {
+    META.with(|meta| meta.borrow().get().clone())
+}
+
+pub fn update_meta<F: FnOnce(&mut CanisterMeta)>(f: F) {
+    META.with(|meta| {
+        let mut cell = meta.borrow_mut();
+        let mut value = cell.get().clone();
+        f(&mut value);
+        cell.set(value).expect("Failed to write meta cell");
+    });
+}
+
+// Record that HOLDER_INFO changed and return the new snapshot id
+pub fn bump_snapshot(current_time: u64) -> u64 {
+    let mut snapshot_id = 0;
+    update_meta(|meta| {
+        snapshot_id = meta.snapshot_id.unwrap_or(0) + 1;
+        meta.snapshot_id = Some(snapshot_id);
+        meta.snapshot_at = Some(current_time);
+    });
+    snapshot_id
+}
+
+pub fn set_stable_string(cell: &'static std::thread::LocalKey<RefCell<StableCell<String, Memory>>>, value: String) {
+    cell.with(|data| {
+        data.borrow_mut().set(value).expect("Failed to write CSV cell");
+    });
+}
+
+pub fn stored_schema_version() -> u32 {
+    STORED_SCHEMA_VERSION.with(|version| *version.borrow().get())
+}
+
+// Stamp a freshly installed canister with the current schema version
+pub fn init_schema() {
+    collections::seed_default_collections();
+    set_schema_version(SCHEMA_VERSION);
+}
+
+fn set_schema_version(value: u32) {
+    STORED_SCHEMA_VERSION.with(|version| {
+        version.borrow_mut().set(value).expect("Failed to write schema version");
+    });
+}
+
+// Bring stored data up to the current schema after an upgrade.
+// Version 0 means the previous build kept everything on the heap, so there is nothing to convert.
+pub fn migrate_schema() {
+    let stored = stored_schema_version();
+    if stored > SCHEMA_VERSION {
+        ic_cdk::trap(&format!(
+            "Stable memory schema v{} is newer than this build (v{}); refusing to downgrade",
+            stored, SCHEMA_VERSION
+        ));
+    }
+
+    if stored < SCHEMA_VERSION {
+        ic_cdk::print(format!("Migrating stable schema from v{} to v{}", stored, SCHEMA_VERSION));
+    }
+
+    if stored < 2 {
+        collections::seed_default_collections();
+        backfill_collection_holdings(&HOLDER_INFO);
+        backfill_collection_holdings(&KNOWN_HOLDERS);
+    }
+
+    set_schema_version(SCHEMA_VERSION);
+}
+
+// v1 -> v2: derive per-collection counts from the legacy Daku/GG fields
+fn backfill_collection_holdings(map: &'static std::thread::LocalKey<RefCell<StableBTreeMap<StablePrincipal, HolderInfo, Memory>>>) {
+    map.with(|map| {
+        let mut map = map.borrow_mut();
+        let legacy: Vec<(StablePrincipal, HolderInfo)> = map.iter()
+            .filter(|(_, info)| info.collections.is_none())
+            .collect();
+        for (key, info) in legacy {
+            let upgraded = HolderInfo::from_holdings(info.holdings(), info.last_updated);
+            map.insert(key, upgraded);
+        }
+    });
+}
@@ -0,0 +1,154 @@
+use candid::Principal;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::nft_registry_interface::TokenIndex;
+use crate::state::{self, read_principal, write_principal, Memory, StablePrincipal, HOLDER_TOKENS_MEMORY_ID, PRINCIPAL_SLOT, TOKEN_OWNERS_MEMORY_ID};
+
+fn read_index(bytes: &[u8]) -> TokenIndex {
+    TokenIndex::from_be_bytes(bytes.try_into().expect("token index is 4 bytes"))
+}
+
+// (collection, token) -> owner
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct TokenKey {
+    collection: Principal,
+    index: TokenIndex,
+}
+
+impl Storable for TokenKey {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = Vec::with_capacity(Self::MAX_SIZE as usize);
+        write_principal(&mut bytes, &self.collection);
+        bytes.extend_from_slice(&self.index.to_be_bytes());
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        TokenKey {
+            collection: read_principal(&bytes),
+            index: read_index(&bytes[PRINCIPAL_SLOT..]),
+        }
+    }
+}
+
+impl BoundedStorable for TokenKey {
+    const MAX_SIZE: u32 = PRINCIPAL_SLOT as u32 + 4;
+    const IS_FIXED_SIZE: bool = true;
+}
+
+// (holder, collection, token), the reverse index used by get_tokens_of
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct HolderTokenKey {
+    holder: Principal,
+    collection: Principal,
+    index: TokenIndex,
+}
+
+impl Storable for HolderTokenKey {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = Vec::with_capacity(Self::MAX_SIZE as usize);
+        write_principal(&mut bytes, &self.holder);
+        write_principal(&mut bytes, &self.collection);
+        bytes.extend_from_slice(&self.index.to_be_bytes());
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        HolderTokenKey {
+            holder: read_principal(&bytes),
+            collection: read_principal(&bytes[PRINCIPAL_SLOT..]),
+            index: read_index(&bytes[2 * PRINCIPAL_SLOT..]),
+        }
+    }
+}
+
+impl BoundedStorable for HolderTokenKey {
+    const MAX_SIZE: u32 = 2 * PRINCIPAL_SLOT as u32 + 4;
+    const IS_FIXED_SIZE: bool = true;
+}
+
+thread_local! {
+    static TOKEN_OWNERS: RefCell<StableBTreeMap<TokenKey, StablePrincipal, Memory>> =
+        RefCell::new(StableBTreeMap::init(state::memory(TOKEN_OWNERS_MEMORY_ID)));
+
+    static HOLDER_TOKENS: RefCell<StableBTreeMap<HolderTokenKey, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(state::memory(HOLDER_TOKENS_MEMORY_ID)));
+}
+
+pub fn owner_of(collection: &Principal, index: TokenIndex) -> Option<Principal> {
+    TOKEN_OWNERS.with(|owners| {
+        owners.borrow().get(&TokenKey { collection: *collection, index }).map(|owner| owner.0)
+    })
+}
+
+pub fn tokens_of(holder: &Principal, collection: &Principal) -> Vec<TokenIndex> {
+    let start = HolderTokenKey { holder: *holder, collection: *collection, index: TokenIndex::MIN };
+    let end = HolderTokenKey { index: TokenIndex::MAX, ..start };
+    HOLDER_TOKENS.with(|tokens| {
+        tokens.borrow().range(start..=end).map(|(key, _)| key.index).collect()
+    })
+}
+
+// Every token of `collection` with its current owner, in index order
+fn collection_tokens(collection: &Principal) -> Vec<(TokenIndex, Principal)> {
+    let start = TokenKey { collection: *collection, index: TokenIndex::MIN };
+    let end = TokenKey { index: TokenIndex::MAX, ..start };
+    TOKEN_OWNERS.with(|owners| {
+        owners.borrow().range(start..=end).map(|(key, owner)| (key.index, owner.0)).collect()
+    })
+}
+
+fn assign(collection: &Principal, index: TokenIndex, owner: &Principal) {
+    unassign(collection, index);
+    TOKEN_OWNERS.with(|owners| {
+        owners.borrow_mut().insert(TokenKey { collection: *collection, index }, StablePrincipal(*owner));
+    });
+    HOLDER_TOKENS.with(|tokens| {
+        tokens.borrow_mut().insert(HolderTokenKey { holder: *owner, collection: *collection, index }, ());
+    });
+}
+
+fn unassign(collection: &Principal, index: TokenIndex) {
+    let previous = TOKEN_OWNERS.with(|owners| {
+        owners.borrow_mut().remove(&TokenKey { collection: *collection, index })
+    });
+    if let Some(previous) = previous {
+        HOLDER_TOKENS.with(|tokens| {
+            tokens.borrow_mut().remove(&HolderTokenKey { holder: previous.0, collection: *collection, index });
+        });
+    }
+}
+
+// Make `tokens` the complete ownership list of `collection`. Only entries whose
+// owner actually changed are written, so a refresh with few transfers stays cheap.
+pub fn replace_collection(collection: &Principal, tokens: &[(TokenIndex, Principal)]) {
+    let wanted: HashMap<TokenIndex, Principal> = tokens.iter().copied().collect();
+    for (index, owner) in collection_tokens(collection) {
+        if wanted.get(&index) != Some(&owner) {
+            unassign(collection, index);
+        }
+    }
+    for (index, owner) in &wanted {
+        if owner_of(collection, *index) != Some(*owner) {
+            assign(collection, *index, owner);
+        }
+    }
+}
+
+// Set the tokens `holder` owns in `collection`, leaving other holders' tokens alone
+// unless one of them is now listed for `holder`
+pub fn replace_holder_tokens(collection: &Principal, holder: &Principal, indices: &[TokenIndex]) {
+    for index in tokens_of(holder, collection) {
+        if !indices.contains(&index) {
+            unassign(collection, index);
+        }
+    }
+    for index in indices {
+        if owner_of(collection, *index) != Some(*holder) {
+            assign(collection, *index, holder);
+        }
+    }
+}